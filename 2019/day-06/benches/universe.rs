@@ -0,0 +1,45 @@
+use std::time::Instant;
+use day_06::Universe;
+
+// a chain as deep as `depth` with the rest of the objects hanging off random earlier ones,
+// YOU and SAN orbit the last chain object and the last random one
+fn generate(depth: usize, count: usize) -> Vec<String> {
+  let name = |index: usize| if index == 0 { String::from("COM") } else { format!("O{}", index) };
+  let mut seed: u64 = 0x2545_f491_4f6c_dd1d;
+  let mut orbits = Vec::with_capacity(count + 2);
+
+  for index in 1..count {
+    let parent = if index < depth {
+      index - 1
+    } else {
+      seed ^= seed << 13;
+      seed ^= seed >> 7;
+      seed ^= seed << 17;
+      (seed % index as u64) as usize
+    };
+    orbits.push(format!("{}){}", name(parent), name(index)));
+  }
+  orbits.push(format!("{})YOU", name(depth - 1)));
+  orbits.push(format!("{})SAN", name(count - 1)));
+
+  orbits
+}
+
+
+// a million objects, a quarter of them in one chain
+fn main() {
+  let orbits = generate(250_000, 1_000_000);
+
+  let start = Instant::now();
+  let mut universe = Universe::new();
+  universe.process_orbits(&orbits);
+  let processed = start.elapsed();
+  let total = universe.total_orbits();
+  let counted = start.elapsed();
+  let transfers = universe.total_transfers("YOU", "SAN");
+  let finished = start.elapsed();
+
+  println!("processed {} objects in {:?}", orbits.len() + 1, processed);
+  println!("total orbits {} after {:?}", total, counted - processed);
+  println!("{} transfers after {:?}", transfers, finished - counted);
+}
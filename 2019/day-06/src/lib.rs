@@ -0,0 +1,249 @@
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::fs;
+
+const CENTER: &str = "COM";
+
+#[derive(Debug)]
+struct SpaceObject {
+  name: String,
+  parent: Option<usize>,
+  orbiting_objects: Vec<usize>
+}
+
+impl SpaceObject {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: String::from(name),
+      parent: None,
+      orbiting_objects: Vec::new()
+    }
+  }
+}
+
+// depths plus binary lifting tables, ancestors[k][i] is the object 2^k steps closer to the center
+// than object i (or the center itself once there is nothing further in)
+struct Lifting {
+  depths: Vec<u32>,
+  ancestors: Vec<Vec<u32>>
+}
+
+#[derive(Default)]
+pub struct Universe {
+  // every object lives in the arena and refers to the others by index
+  objects: Vec<SpaceObject>,
+  indices: HashMap<String, usize>,
+  // built on first use and thrown away whenever more orbits are processed
+  lifting: OnceCell<Lifting>
+}
+
+impl Universe {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  fn index_of(&mut self, name: &str) -> usize {
+    if let Some(index) = self.indices.get(name) {
+      return *index;
+    }
+
+    let index = self.objects.len();
+    self.objects.push(SpaceObject::new(name));
+    self.indices.insert(String::from(name), index);
+    index
+  }
+
+  pub fn process_orbits(&mut self, orbits: &[String]) {
+    for el in orbits.iter() {
+      let (inner_name, outer_name) = el.trim().split_once(')').expect("Orbit is missing a )");
+      let (inner, outer) = (self.index_of(inner_name), self.index_of(outer_name));
+      if let Some(parent) = self.objects[outer].parent {
+        panic!("{} already orbits {}", outer_name, self.objects[parent].name);
+      }
+
+      self.objects[inner].orbiting_objects.push(outer);
+      self.objects[outer].parent = Some(inner);
+    }
+
+    self.lifting = OnceCell::new();
+  }
+
+  fn find(&self, name: &str) -> usize {
+    match self.indices.get(name) {
+      Some(index) => *index,
+      None => panic!("{} is not in the universe", name)
+    }
+  }
+
+  fn lifting(&self) -> &Lifting {
+    self.lifting.get_or_init(|| {
+      let count = self.objects.len();
+      let mut depths = vec![0_u32; count];
+      let mut order = Vec::with_capacity(count);
+
+      // breadth first from the center, so parents are always handled before their satellites
+      let center = self.find(CENTER);
+      if let Some(parent) = self.objects[center].parent {
+        panic!("{} orbits {}", CENTER, self.objects[parent].name);
+      }
+      order.push(center);
+      let mut next = 0;
+      while next < order.len() {
+        let index = order[next];
+        for satellite in self.objects[index].orbiting_objects.iter() {
+          depths[*satellite] = depths[index] + 1;
+          order.push(*satellite);
+        }
+        next += 1;
+      }
+      // every object has a single parent, so anything left over hangs off another root or a cycle
+      if order.len() != count {
+        let mut reached = vec![false; count];
+        order.iter().for_each(|index| reached[*index] = true);
+        let missing: Vec<&str> = (0..count)
+          .filter(|index| !reached[*index])
+          .map(|index| self.objects[index].name.as_str())
+          .collect();
+        let shown = missing.iter().take(5).copied().collect::<Vec<&str>>().join(", ");
+        panic!("{} objects are not connected to {}, starting with {}", missing.len(), CENTER, shown);
+      }
+
+      let parents: Vec<u32> = (0..count)
+        .map(|index| self.objects[index].parent.unwrap_or(index) as u32)
+        .collect();
+      let deepest = depths.iter().copied().max().unwrap_or(0);
+      let mut ancestors = vec![parents];
+      while 1_u64 << ancestors.len() <= deepest as u64 {
+        let previous = ancestors.last().unwrap();
+        let level = previous.iter().map(|ancestor| previous[*ancestor as usize]).collect();
+        ancestors.push(level);
+      }
+
+      Lifting { depths, ancestors }
+    })
+  }
+
+  pub fn total_orbits(&self) -> usize {
+    if !self.indices.contains_key(CENTER) {
+      panic!("Universe is not created yet");
+    }
+
+    self.lifting().depths.iter().map(|depth| *depth as usize).sum()
+  }
+
+  fn lowest_common_ancestor(&self, a: usize, b: usize) -> usize {
+    let lifting = self.lifting();
+    let (mut a, mut b) = (a as u32, b as u32);
+    if lifting.depths[a as usize] < lifting.depths[b as usize] {
+      std::mem::swap(&mut a, &mut b);
+    }
+
+    let difference = lifting.depths[a as usize] - lifting.depths[b as usize];
+    for (level, ancestors) in lifting.ancestors.iter().enumerate() {
+      if difference & (1 << level) != 0 {
+        a = ancestors[a as usize];
+      }
+    }
+    if a == b {
+      return a as usize;
+    }
+
+    for ancestors in lifting.ancestors.iter().rev() {
+      if ancestors[a as usize] != ancestors[b as usize] {
+        a = ancestors[a as usize];
+        b = ancestors[b as usize];
+      }
+    }
+
+    lifting.ancestors[0][a as usize] as usize
+  }
+
+  // transfers between the objects o1 and o2 are orbiting
+  pub fn total_transfers(&self, o1: &str, o2: &str) -> usize {
+    let parent = |name: &str| {
+      let object = &self.objects[self.find(name)];
+      object.parent.unwrap_or_else(|| panic!("{} is not orbiting anything", object.name))
+    };
+    let (p1, p2) = (parent(o1), parent(o2));
+
+    let depths = &self.lifting().depths;
+    let common = self.lowest_common_ancestor(p1, p2);
+    (depths[p1] + depths[p2] - 2 * depths[common]) as usize
+  }
+}
+
+pub fn get_space_objects(filename: &str) -> Vec<String> {
+  let contents = fs::read_to_string(filename)
+      .expect("Something went wrong reading the file");
+  let space_objects: Vec<String> = contents
+    .lines()
+    .map(ToOwned::to_owned)
+    .collect();
+
+  space_objects
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_orbits() {
+    let space_objects = get_space_objects("test.txt");
+
+    let mut universe = Universe::new();
+    universe.process_orbits(&space_objects);
+
+    assert_eq!(universe.total_orbits(), 42);
+  }
+
+  #[test]
+  fn test_paths() {
+    let space_objects = get_space_objects("test2.txt");
+
+    let mut universe = Universe::new();
+    universe.process_orbits(&space_objects);
+
+    assert_eq!(universe.total_transfers("YOU", "SAN"), 4);
+    assert_eq!(universe.total_transfers("SAN", "YOU"), 4);
+    assert_eq!(universe.total_transfers("YOU", "YOU"), 0);
+    assert_eq!(universe.total_transfers("K", "YOU"), 1);
+  }
+
+  #[test]
+  fn test_deep_chain() {
+    let depth = 100_000;
+    let name = |index: usize| if index == 0 { String::from(CENTER) } else { format!("O{}", index) };
+    let mut orbits: Vec<String> = (1..depth).map(|index| format!("{}){}", name(index - 1), name(index))).collect();
+    orbits.push(format!("{})YOU", name(depth - 1)));
+    orbits.push(format!("{})SAN", name(depth - 1)));
+
+    let mut universe = Universe::new();
+    universe.process_orbits(&orbits);
+
+    // COM through O99999 plus YOU and SAN, both orbiting O99999
+    let chain: usize = (0..depth).sum();
+    assert_eq!(universe.total_orbits(), chain + 2 * depth);
+    assert_eq!(universe.total_transfers("YOU", "SAN"), 0);
+    assert_eq!(universe.total_transfers("YOU", "O1"), depth - 1);
+  }
+
+  #[test]
+  #[should_panic(expected = "B already orbits A")]
+  fn test_second_parent() {
+    let orbits: Vec<String> = vec![String::from("COM)A"), String::from("A)B"), String::from("COM)B")];
+
+    Universe::new().process_orbits(&orbits);
+  }
+
+  #[test]
+  #[should_panic(expected = "4 objects are not connected to COM, starting with X, Y, YOU, SAN")]
+  fn test_disconnected() {
+    // YOU and SAN share a parent, but nothing links X to the center
+    let orbits: Vec<String> = ["COM)A", "X)Y", "Y)YOU", "Y)SAN"].iter().map(|el| String::from(*el)).collect();
+    let mut universe = Universe::new();
+    universe.process_orbits(&orbits);
+
+    universe.total_transfers("YOU", "SAN");
+  }
+}
@@ -0,0 +1,111 @@
+// the day 9 interpreter from before the intcode crate, trimmed to what the benchmark needs and
+// kept as the yardstick: every parameter is decoded digit by digit and memory is a flat vector
+// with some room to grow
+
+enum ParameterMode {
+  Position,
+  Immediate,
+  Relative
+}
+
+pub struct Machine {
+  pc: usize,
+  memory: Vec<i64>,
+  input: Vec<i64>,
+  pub output: Vec<i64>,
+  relative_offset: i64
+}
+
+impl Machine {
+  pub fn new(memory: Vec<i64>, input: Vec<i64>) -> Self {
+    let mut full_memory = memory;
+    full_memory.append(&mut vec![0; 10000]);
+    Self {
+      pc: 0,
+      memory: full_memory,
+      input,
+      output: Vec::new(),
+      relative_offset: 0
+    }
+  }
+
+  fn get_opcode(&self) -> i64 {
+    self.memory[self.pc] % 100
+  }
+
+  fn get_param_mode(&self, offset: usize) -> ParameterMode {
+    match (self.memory[self.pc] as usize / (10_usize.pow(offset as u32 + 1))) % 10 {
+      2 => ParameterMode::Relative,
+      1 => ParameterMode::Immediate,
+      _ => ParameterMode::Position
+    }
+  }
+
+  fn get_param_value(&self, offset: usize) -> i64 {
+    let address = self.get_address(offset);
+
+    self.memory[address]
+  }
+
+  fn get_address(&self, offset: usize) -> usize {
+    let memory = &self.memory;
+    let address = self.pc + offset;
+
+    match self.get_param_mode(offset) {
+      ParameterMode::Position => memory[address] as usize,
+      ParameterMode::Relative => (memory[address] + self.relative_offset) as usize,
+      ParameterMode::Immediate => address
+    }
+  }
+
+  pub fn execute(&mut self) {
+    loop {
+      let opcode = self.get_opcode();
+
+      let step = match opcode {
+        1 | 2 | 7 | 8 => {
+          let (p1, p2) = (self.get_param_value(1), self.get_param_value(2));
+          let address = self.get_address(3);
+          self.memory[address] = match opcode {
+            1 => p1 + p2,
+            2 => p1 * p2,
+            7 => (p1 < p2) as i64,
+            _ => (p1 == p2) as i64
+          };
+
+          4
+        },
+        3 => {
+          let address = self.get_address(1);
+          self.memory[address] = self.input.remove(0);
+
+          2
+        },
+        4 => {
+          let value = self.get_param_value(1);
+          self.output.push(value);
+
+          2
+        },
+        5 | 6 => {
+          let value = self.get_param_value(1);
+          if (value != 0) == (opcode == 5) {
+            self.pc = self.get_param_value(2) as usize;
+            0
+          } else {
+            3
+          }
+        },
+        9 => {
+          self.relative_offset += self.get_param_value(1);
+
+          2
+        },
+        99 => break,
+        _ => panic!("Invalid Opcode: {} @ {}", opcode, self.pc)
+      };
+
+      self.pc += step;
+    }
+  }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use intcode::assemble::assemble;
+use intcode::loader;
+use intcode::{Machine, State};
+
+mod baseline;
+
+const RUNS: u32 = 5;
+
+// sums 1..=n through the relative base and a scratch cell, a few million instructions for the
+// default n and a mix of every addressing mode
+fn synthetic() -> Vec<i64> {
+  assemble("
+          arb #100
+    loop: add rb+0, #1, rb+0
+          add [total], rb+0, [total]
+          lt  rb+0, [n], [more]
+          jt  [more], #loop
+          out [total]
+          hlt
+    total: .data 0
+    n:     .data 1000000
+    more:  .data 0
+  ").unwrap()
+}
+
+// best of a few runs, the setup is outside the timed part
+fn time<F: FnMut() -> Vec<i64>>(mut run: F) -> (Duration, Vec<i64>) {
+  let mut best = Duration::MAX;
+  let mut output = Vec::new();
+
+  for _ in 0..RUNS {
+    let start = Instant::now();
+    output = run();
+    best = best.min(start.elapsed());
+  }
+
+  (best, output)
+}
+
+// the pre-crate interpreter against Machine one step at a time and Machine::run, which takes
+// the fast path wherever it can
+fn compare(name: &str, program: &[i64], input: &[i64]) {
+  let (baseline, expected) = time(|| {
+    let mut machine = baseline::Machine::new(program.to_vec(), input.to_vec());
+    machine.execute();
+    machine.output
+  });
+  let (stepped, output) = time(|| {
+    let mut machine = Machine::new(program.to_vec(), input.to_vec());
+    let mut output = Vec::new();
+    loop {
+      match machine.step().unwrap() {
+        Some(State::Output(value)) => output.push(value),
+        Some(_) => return output,
+        None => {}
+      }
+    }
+  });
+  assert_eq!(output, expected, "{}: stepping changed the output", name);
+  let (fast, output) = time(|| Machine::new(program.to_vec(), input.to_vec()).run().unwrap());
+  assert_eq!(output, expected, "{}: the fast path changed the output", name);
+
+  println!(
+    "{:<12} baseline {:>10.2?}  stepped {:>10.2?} ({:.2}x)  run {:>10.2?} ({:.2}x)",
+    name,
+    baseline,
+    stepped,
+    baseline.as_secs_f64() / stepped.as_secs_f64(),
+    fast,
+    baseline.as_secs_f64() / fast.as_secs_f64()
+  );
+}
+
+fn main() {
+  compare("synthetic", &synthetic(), &[]);
+
+  // BOOST part 2 from day 9, when the puzzle input is around
+  let boost = Path::new(env!("CARGO_MANIFEST_DIR")).join("../day-09/input.txt");
+  if let Ok(contents) = fs::read_to_string(boost) {
+    compare("boost", &loader::parse(&contents).unwrap(), &[2]);
+  }
+}
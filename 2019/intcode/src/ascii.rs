@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use crate::error::VmError;
+use crate::machine::{Machine, State};
+
+#[derive(Debug)]
+pub enum AsciiError {
+  Vm(VmError),
+  NonAscii(char),
+  BudgetExceeded(usize),
+  Io(io::Error)
+}
+
+impl fmt::Display for AsciiError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      AsciiError::Vm(error) => write!(f, "{}", error),
+      AsciiError::NonAscii(c) => write!(f, "cannot send non-ASCII character {:?}", c),
+      AsciiError::BudgetExceeded(pc) => write!(f, "budget exceeded @ {}", pc),
+      AsciiError::Io(error) => write!(f, "{}", error)
+    }
+  }
+}
+
+impl Error for AsciiError {}
+
+impl From<VmError> for AsciiError {
+  fn from(error: VmError) -> Self {
+    AsciiError::Vm(error)
+  }
+}
+
+impl From<io::Error> for AsciiError {
+  fn from(error: io::Error) -> Self {
+    AsciiError::Io(error)
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AsciiOutput {
+  pub text: String,
+  // outputs outside the ASCII range, usually the actual puzzle answer
+  pub values: Vec<i64>
+}
+
+pub struct AsciiMachine {
+  pub machine: Machine
+}
+
+impl AsciiMachine {
+  pub fn new(machine: Machine) -> Self {
+    Self { machine }
+  }
+
+  // queues the line as character codes followed by a newline
+  pub fn send_line(&mut self, line: &str) -> Result<(), AsciiError> {
+    if let Some(c) = line.chars().find(|c| !c.is_ascii()) {
+      return Err(AsciiError::NonAscii(c));
+    }
+
+    line.bytes().for_each(|byte| self.machine.add_input(byte as i64));
+    self.machine.add_input('\n' as i64);
+
+    Ok(())
+  }
+
+  // runs until the machine wants more input or halts, decoding everything it printed
+  pub fn read(&mut self) -> Result<AsciiOutput, AsciiError> {
+    let mut output = AsciiOutput::default();
+    if self.machine.is_halted() {
+      return Ok(output);
+    }
+
+    loop {
+      match self.machine.execute()? {
+        State::Output(value) if (0..128).contains(&value) => output.text.push(value as u8 as char),
+        State::Output(value) => output.values.push(value),
+        State::BudgetExceeded(pc) => return Err(AsciiError::BudgetExceeded(pc)),
+        State::NeedsInput | State::Halted => return Ok(output)
+      }
+    }
+  }
+
+  pub fn is_halted(&self) -> bool {
+    self.machine.is_halted()
+  }
+
+  // pipes lines from input into the machine and its decoded output back out until it halts
+  // or input runs out
+  pub fn interactive<R: BufRead, W: Write>(&mut self, mut input: R, mut output: W) -> Result<(), AsciiError> {
+    loop {
+      let decoded = self.read()?;
+      write!(output, "{}", decoded.text)?;
+      for value in decoded.values {
+        writeln!(output, "{}", value)?;
+      }
+      output.flush()?;
+
+      if self.is_halted() {
+        return Ok(());
+      }
+
+      let mut line = String::new();
+      if input.read_line(&mut line)? == 0 {
+        return Ok(());
+      }
+      self.send_line(line.trim_end_matches(['\n', '\r']))?;
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+
+  // echoes a name, then prints its length plus 1000 as a raw value
+  fn greeter() -> Machine {
+    let program = assemble("
+              out #72
+              out #105
+              out #63
+              out #10
+      read:   in  [char]
+              eq  [char], #10, [done]
+              jt  [done], #finish
+              out [char]
+              add [count], #1, [count]
+              jt  #1, #read
+      finish: out #10
+              add [count], #1000, [count]
+              out [count]
+              hlt
+      char:   .data 0
+      done:   .data 0
+      count:  .data 0
+    ").unwrap();
+
+    Machine::new(program, Vec::new())
+  }
+
+  #[test]
+  fn test_read_and_send() {
+    let mut machine = AsciiMachine::new(greeter());
+
+    assert_eq!(machine.read().unwrap(), AsciiOutput {
+      text: String::from("Hi?\n"),
+      values: Vec::new()
+    });
+
+    machine.send_line("bob").unwrap();
+    assert_eq!(machine.read().unwrap(), AsciiOutput {
+      text: String::from("bob\n"),
+      values: vec![1003]
+    });
+    assert!(machine.is_halted());
+  }
+
+  #[test]
+  fn test_non_ascii_input() {
+    let mut machine = AsciiMachine::new(greeter());
+
+    assert!(matches!(machine.send_line("héllo"), Err(AsciiError::NonAscii('é'))));
+  }
+
+  #[test]
+  fn test_interactive() {
+    let mut machine = AsciiMachine::new(greeter());
+    let mut output = Vec::new();
+    machine.interactive("alice\nignored\n".as_bytes(), &mut output).unwrap();
+
+    assert_eq!(String::from_utf8(output).unwrap(), "Hi?\nalice\n1005\n");
+  }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use crate::instruction::{Opcode, ParameterMode};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AsmError {
+  pub line: usize,
+  pub message: String
+}
+
+impl fmt::Display for AsmError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "line {}: {}", self.line, self.message)
+  }
+}
+
+impl Error for AsmError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+  Number(i64),
+  Label(String, i64)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Operand {
+  mode: ParameterMode,
+  value: Value
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Statement {
+  Instruction(Opcode, Vec<Operand>),
+  Data(Vec<Value>)
+}
+
+impl Statement {
+  fn size(&self) -> usize {
+    match self {
+      Statement::Instruction(_, operands) => operands.len() + 1,
+      Statement::Data(values) => values.len()
+    }
+  }
+}
+
+fn error(line: usize, message: String) -> AsmError {
+  AsmError { line, message }
+}
+
+fn is_identifier(text: &str) -> bool {
+  let mut chars = text.chars();
+  match chars.next() {
+    Some(c) if c.is_ascii_alphabetic() || c == '_' => {},
+    _ => return false
+  }
+
+  chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && text != "rb"
+}
+
+fn parse_value(text: &str, line: usize) -> Result<Value, AsmError> {
+  let text = text.trim();
+  if let Ok(number) = text.parse() {
+    return Ok(Value::Number(number));
+  }
+
+  // label, optionally followed by a constant offset such as `buffer+2`
+  let split = text.find(['+', '-']).unwrap_or(text.len());
+  let (label, offset) = text.split_at(split);
+  let label = label.trim();
+  if !is_identifier(label) {
+    return Err(error(line, format!("invalid value `{}`", text)));
+  }
+
+  let offset = match offset.replace(' ', "").as_str() {
+    "" => 0,
+    offset => offset
+      .trim_start_matches('+')
+      .parse()
+      .map_err(|_| error(line, format!("invalid offset in `{}`", text)))?
+  };
+
+  Ok(Value::Label(String::from(label), offset))
+}
+
+fn parse_operand(text: &str, line: usize) -> Result<Operand, AsmError> {
+  let text = text.trim();
+
+  let (mode, value) = if let Some(rest) = text.strip_prefix('#') {
+    (ParameterMode::Immediate, parse_value(rest, line)?)
+  } else if text.starts_with('[') && text.ends_with(']') {
+    (ParameterMode::Position, parse_value(&text[1..text.len() - 1], line)?)
+  } else if text == "rb" {
+    (ParameterMode::Relative, Value::Number(0))
+  } else if let Some(rest) = text.strip_prefix("rb+") {
+    (ParameterMode::Relative, parse_value(rest, line)?)
+  } else if let Some(rest) = text.strip_prefix("rb-") {
+    let value = rest.trim().parse::<i64>()
+      .map_err(|_| error(line, format!("invalid relative offset `{}`", text)))?;
+    (ParameterMode::Relative, Value::Number(-value))
+  } else {
+    (ParameterMode::Position, parse_value(text, line)?)
+  };
+
+  Ok(Operand { mode, value })
+}
+
+fn split_list(text: &str) -> Vec<&str> {
+  if text.trim().is_empty() {
+    Vec::new()
+  } else {
+    text.split(',').map(|el| el.trim()).collect()
+  }
+}
+
+fn parse_statement(text: &str, line: usize) -> Result<Statement, AsmError> {
+  let (head, rest) = match text.find(char::is_whitespace) {
+    Some(index) => text.split_at(index),
+    None => (text, "")
+  };
+
+  if head == ".data" {
+    let values = split_list(rest)
+      .into_iter()
+      .map(|value| parse_value(value, line))
+      .collect::<Result<Vec<Value>, AsmError>>()?;
+    if values.is_empty() {
+      return Err(error(line, String::from(".data needs at least one value")));
+    }
+
+    return Ok(Statement::Data(values));
+  }
+
+  let opcode = Opcode::from_mnemonic(head)
+    .ok_or_else(|| error(line, format!("unknown mnemonic `{}`", head)))?;
+  let operands = split_list(rest)
+    .into_iter()
+    .map(|operand| parse_operand(operand, line))
+    .collect::<Result<Vec<Operand>, AsmError>>()?;
+
+  if operands.len() != opcode.param_count() {
+    return Err(error(line, format!(
+      "`{}` takes {} operands, found {}", head, opcode.param_count(), operands.len()
+    )));
+  }
+  if let Some(offset) = opcode.write_param() {
+    if operands[offset - 1].mode == ParameterMode::Immediate {
+      return Err(error(line, format!("`{}` cannot write to an immediate", head)));
+    }
+  }
+
+  Ok(Statement::Instruction(opcode, operands))
+}
+
+fn resolve(value: &Value, labels: &HashMap<String, usize>, line: usize) -> Result<i64, AsmError> {
+  match value {
+    Value::Number(number) => Ok(*number),
+    Value::Label(label, offset) => {
+      let address = labels.get(label).ok_or_else(|| error(line, format!("undefined label `{}`", label)))?;
+      (*address as i64)
+        .checked_add(*offset)
+        .ok_or_else(|| error(line, format!("`{}{:+}` overflows", label, offset)))
+    }
+  }
+}
+
+fn mode_digit(mode: ParameterMode) -> i64 {
+  match mode {
+    ParameterMode::Position => 0,
+    ParameterMode::Immediate => 1,
+    ParameterMode::Relative => 2
+  }
+}
+
+// source lines look like `label: mnemonic operand, operand ; comment`, where an operand is
+// `[x]` or a bare `x` for position mode, `#x` for immediate and `rb+x` for relative mode
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+  let mut labels = HashMap::new();
+  let mut statements = Vec::new();
+  let mut address = 0;
+
+  for (index, text) in source.lines().enumerate() {
+    let line = index + 1;
+    let mut text = text.split(';').next().unwrap().trim();
+
+    while let Some(colon) = text.find(':') {
+      let label = text[..colon].trim();
+      if !is_identifier(label) {
+        break;
+      }
+      if labels.insert(String::from(label), address).is_some() {
+        return Err(error(line, format!("duplicate label `{}`", label)));
+      }
+      text = text[colon + 1..].trim();
+    }
+
+    if text.is_empty() {
+      continue;
+    }
+
+    let statement = parse_statement(text, line)?;
+    address += statement.size();
+    statements.push((line, statement));
+  }
+
+  let mut program = Vec::with_capacity(address);
+  for (line, statement) in statements {
+    match statement {
+      Statement::Instruction(opcode, operands) => {
+        let modes: i64 = operands
+          .iter()
+          .enumerate()
+          .map(|(offset, operand)| mode_digit(operand.mode) * 10_i64.pow(offset as u32 + 2))
+          .sum();
+        program.push(opcode.code() + modes);
+
+        for operand in operands {
+          program.push(resolve(&operand.value, &labels, line)?);
+        }
+      },
+      Statement::Data(values) => {
+        for value in values {
+          program.push(resolve(&value, &labels, line)?);
+        }
+      }
+    }
+  }
+
+  Ok(program)
+}
+
+pub fn to_program_string(program: &[i64]) -> String {
+  let values: Vec<String> = program.iter().map(|el| el.to_string()).collect();
+  values.join(",")
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::disassemble::{disassemble, Line};
+  use crate::machine::Machine;
+
+  #[test]
+  fn test_assemble() {
+    let source = "
+      ; prints the square of its input
+      start: arb  #100
+             in   [x]
+             mul  [x], x, rb+2
+             out  rb+2
+             jf   #0, #end
+      x:     .data 0
+      end:   hlt
+    ";
+
+    let program = assemble(source).unwrap();
+    assert_eq!(to_program_string(&program), "109,100,3,13,20002,13,13,2,204,2,1106,0,14,0,99");
+
+    let mut machine = Machine::new(program, vec![7]);
+    assert_eq!(machine.run().unwrap(), vec![49]);
+  }
+
+  #[test]
+  fn test_labels_with_offsets() {
+    let program = assemble("out [buffer+1]\nhlt\nbuffer: .data 5, 6, buffer-1").unwrap();
+
+    assert_eq!(program, vec![4, 4, 99, 5, 6, 2]);
+  }
+
+  #[test]
+  fn test_errors() {
+    assert_eq!(assemble("nop").unwrap_err(), AsmError {
+      line: 1,
+      message: String::from("unknown mnemonic `nop`")
+    });
+    assert_eq!(assemble("\nadd #1, #2, #3").unwrap_err().line, 2);
+    assert_eq!(assemble("out #1, #2").unwrap_err().line, 1);
+    assert!(assemble("jt #1, #nowhere").unwrap_err().message.contains("nowhere"));
+    assert!(assemble("a: hlt\na: hlt").unwrap_err().message.contains("duplicate"));
+    assert_eq!(assemble("out [x+9223372036854775807]\nx: hlt").unwrap_err(), AsmError {
+      line: 1,
+      message: String::from("`x+9223372036854775807` overflows")
+    });
+  }
+
+  #[test]
+  fn test_disassembly_round_trip() {
+    let program = vec![
+      109, 20, 21101, 9, 0, 0, 1105, 1, 12, 104, 1, 99, 104, -2, 2105, 1, 0, 7, 8
+    ];
+
+    let source: Vec<String> = disassemble(&program)
+      .iter()
+      .map(|line| match line {
+        Line::Code(instruction) => instruction.to_string(),
+        Line::Data { values, .. } => format!(".data {}", to_program_string(values))
+      })
+      .collect();
+
+    assert_eq!(assemble(&source.join("\n")).unwrap(), program);
+  }
+}
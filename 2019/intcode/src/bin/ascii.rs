@@ -0,0 +1,20 @@
+use std::env;
+use std::io;
+use std::process;
+use intcode::ascii::AsciiMachine;
+use intcode::loader;
+use intcode::Machine;
+
+fn main() {
+  let filename = env::args().nth(1).unwrap_or_else(|| String::from("input.txt"));
+  let program = loader::load(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+
+  let mut machine = AsciiMachine::new(Machine::new(program, Vec::new()));
+  let stdin = io::stdin();
+  if let Err(error) = machine.interactive(stdin.lock(), io::stdout()) {
+    eprintln!("{}", error);
+  }
+}
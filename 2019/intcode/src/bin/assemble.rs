@@ -0,0 +1,20 @@
+use std::env;
+use std::fs;
+use std::process;
+use intcode::assemble;
+
+fn main() {
+  let filename = env::args().nth(1).unwrap_or_else(|| String::from("input.asm"));
+  let source = fs::read_to_string(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+
+  match assemble::assemble(&source) {
+    Ok(program) => println!("{}", assemble::to_program_string(&program)),
+    Err(error) => {
+      eprintln!("{}", error);
+      process::exit(1);
+    }
+  }
+}
@@ -0,0 +1,24 @@
+use std::env;
+use std::process;
+use intcode::cfg;
+use intcode::loader;
+
+// usage: cfg [file] [dot|json]
+fn main() {
+  let filename = env::args().nth(1).unwrap_or_else(|| String::from("input.txt"));
+  let format = env::args().nth(2).unwrap_or_else(|| String::from("dot"));
+  let program = loader::load(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+
+  let graph = cfg::build(&program);
+  match format.as_str() {
+    "dot" => print!("{}", graph.to_dot()),
+    "json" => print!("{}", graph.to_json()),
+    other => {
+      eprintln!("Unknown format {}, expected dot or json", other);
+      process::exit(2);
+    }
+  }
+}
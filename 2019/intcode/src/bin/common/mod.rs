@@ -0,0 +1,10 @@
+use std::process;
+use std::str::FromStr;
+
+// arguments that do not parse are usage errors, not crashes
+pub fn parse<T: FromStr>(arg: &str, usage: &str) -> T {
+  arg.parse().unwrap_or_else(|_| {
+    eprintln!("{}", usage);
+    process::exit(2);
+  })
+}
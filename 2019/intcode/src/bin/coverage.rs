@@ -0,0 +1,35 @@
+mod common;
+
+use std::env;
+use std::process;
+use intcode::coverage::Coverage;
+use intcode::loader;
+use intcode::Machine;
+
+const USAGE: &str = "usage: coverage [file] [text|json] [input...]";
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let filename = args.first().cloned().unwrap_or_else(|| String::from("input.txt"));
+  let format = args.get(1).cloned().unwrap_or_else(|| String::from("text"));
+  let program = loader::load(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+  let input: Vec<i64> = args.iter().skip(2).map(|el| common::parse(el, USAGE)).collect();
+
+  let mut coverage = Coverage::new();
+  let mut machine = Machine::new(program, input);
+  if let Err(error) = machine.run_traced(&mut coverage) {
+    eprintln!("{}", error);
+  }
+
+  match format.as_str() {
+    "text" => println!("{}", coverage.report()),
+    "json" => print!("{}", coverage.to_json()),
+    other => {
+      eprintln!("Unknown format {}, expected text or json", other);
+      process::exit(2);
+    }
+  }
+}
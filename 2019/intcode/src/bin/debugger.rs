@@ -0,0 +1,50 @@
+mod common;
+
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::process;
+use intcode::debugger::Debugger;
+use intcode::loader;
+use intcode::Machine;
+
+const USAGE: &str = "usage: debugger [program] [inputs...]";
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let filename = args.first().cloned().unwrap_or_else(|| String::from("input.txt"));
+  let program = loader::load(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+  let input: Vec<i64> = args.iter().skip(1).map(|el| common::parse(el, USAGE)).collect();
+
+  let mut debugger = Debugger::new(Machine::new(program, input));
+  let stdin = io::stdin();
+  loop {
+    print!("(icdb) ");
+    if let Err(error) = io::stdout().flush() {
+      eprintln!("{}", error);
+      process::exit(1);
+    }
+
+    // a line that is not valid UTF-8 is reported and skipped, the session goes on
+    let mut line = String::new();
+    match stdin.lock().read_line(&mut line) {
+      Ok(0) => break,
+      Ok(_) => {},
+      Err(error) => {
+        println!("error: {}", error);
+        continue;
+      }
+    }
+
+    match line.trim() {
+      "q" | "quit" => break,
+      command => match debugger.command(command) {
+        Ok(text) if text.is_empty() => {},
+        Ok(text) => println!("{}", text),
+        Err(error) => println!("error: {}", error)
+      }
+    }
+  }
+}
@@ -0,0 +1,14 @@
+use std::env;
+use std::process;
+use intcode::disassemble;
+use intcode::loader;
+
+fn main() {
+  let filename = env::args().nth(1).unwrap_or_else(|| String::from("input.txt"));
+  let program = loader::load(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+
+  print!("{}", disassemble::listing(&program));
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use std::env;
+use std::process;
+use intcode::inspect::{self, DumpOptions, Radix, SnapshotDiff};
+use intcode::loader;
+use intcode::snapshot::Snapshot;
+use intcode::Machine;
+
+const USAGE: &str = "\
+usage: inspect dump <program> [dec|hex] [start] [end]
+       inspect run <program> [inputs...]
+       inspect diff <snapshot> <snapshot>";
+
+fn load(filename: &str) -> Vec<i64> {
+  loader::load(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  })
+}
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  if args.len() < 2 {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+  }
+
+  match args[0].as_str() {
+    "dump" => {
+      let machine = Machine::new(load(&args[1]), Vec::new());
+      let radix = match args.get(2).map(|el| el.as_str()) {
+        Some("hex") => Radix::Hex,
+        _ => Radix::Decimal
+      };
+      let start = args.get(3).map(|el| common::parse(el, USAGE)).unwrap_or(0);
+      let end = args.get(4).map(|el| common::parse(el, USAGE)).unwrap_or_else(|| machine.memory.as_slice().len());
+      let options = DumpOptions { radix, ..DumpOptions::default() };
+
+      println!("{}", inspect::dump(&machine.memory, start..end, &options));
+    },
+    // what a run changes, from the freshly loaded program to wherever the machine stopped
+    "run" => {
+      let input: Vec<i64> = args.iter().skip(2).map(|el| common::parse(el, USAGE)).collect();
+      let mut machine = Machine::new(load(&args[1]), input);
+      let before = machine.snapshot();
+      match machine.run() {
+        Ok(output) => println!("output: {:?}", output),
+        Err(error) => println!("error: {}", error)
+      }
+
+      println!("{}", SnapshotDiff::new(&before, &machine.snapshot()));
+    },
+    "diff" if args.len() > 2 => {
+      let snapshots: Vec<Snapshot> = args[1..3]
+        .iter()
+        .map(|filename| Snapshot::load(filename).unwrap_or_else(|error| {
+          eprintln!("{}", error);
+          process::exit(1);
+        }))
+        .collect();
+
+      println!("{}", SnapshotDiff::new(&snapshots[0], &snapshots[1]));
+    },
+    _ => {
+      eprintln!("{}", USAGE);
+      process::exit(2);
+    }
+  }
+}
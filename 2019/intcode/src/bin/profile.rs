@@ -0,0 +1,38 @@
+mod common;
+
+use std::env;
+use std::process;
+use intcode::loader;
+use intcode::trace::Profiler;
+use intcode::{Machine, State};
+
+const USAGE: &str = "usage: profile [program] [inputs...]";
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  let filename = args.first().cloned().unwrap_or_else(|| String::from("input.txt"));
+  let program = loader::load(filename).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+  let input: Vec<i64> = args.iter().skip(1).map(|el| common::parse(el, USAGE)).collect();
+
+  let mut profiler = Profiler::new();
+  let mut machine = Machine::new(program, input);
+  let mut output = Vec::new();
+  // a faulting program still gets the profile of everything it ran up to the fault
+  let result = loop {
+    match machine.execute_traced(&mut profiler) {
+      Ok(State::Output(value)) => output.push(value),
+      Ok(_) => break Ok(()),
+      Err(error) => break Err(error)
+    }
+  };
+
+  println!("output: {:?}", output);
+  println!("{}", profiler.summary(10));
+  if let Err(error) = result {
+    eprintln!("{}", error);
+    process::exit(1);
+  }
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use std::env;
+use std::process;
+use intcode::loader;
+use intcode::transcript::Transcript;
+use intcode::Machine;
+
+const USAGE: &str = "\
+usage: transcript record <program> <transcript> [inputs...]
+       transcript replay <program> <transcript>";
+
+fn main() {
+  let args: Vec<String> = env::args().skip(1).collect();
+  if args.len() < 3 {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+  }
+
+  let program = loader::load(&args[1]).unwrap_or_else(|error| {
+    eprintln!("{}", error);
+    process::exit(1);
+  });
+
+  let result = match args[0].as_str() {
+    "record" => {
+      let input: Vec<i64> = args.iter().skip(3).map(|el| common::parse(el, USAGE)).collect();
+      Transcript::record(&program, &input).and_then(|transcript| {
+        println!("recorded {} entries", transcript.entries.len());
+        transcript.save(&args[2])
+      })
+    },
+    "replay" => Transcript::load(&args[2]).and_then(|transcript| {
+      transcript.replay(Machine::new(program, Vec::new()))?;
+      println!("replayed {} entries", transcript.entries.len());
+      Ok(())
+    }),
+    other => {
+      eprintln!("Unknown command {}, expected record or replay", other);
+      process::exit(2);
+    }
+  };
+
+  if let Err(error) = result {
+    eprintln!("{}", error);
+    process::exit(1);
+  }
+}
@@ -0,0 +1,285 @@
+use std::collections::{BTreeSet, HashSet};
+use crate::disassemble::find_code;
+use crate::instruction::Instruction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+  // the next instruction in memory
+  Fallthrough,
+  // an immediate-mode jump target
+  Taken
+}
+
+impl EdgeKind {
+  fn name(self) -> &'static str {
+    match self {
+      EdgeKind::Fallthrough => "fallthrough",
+      EdgeKind::Taken => "taken"
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edge {
+  pub from: usize,
+  pub to: usize,
+  pub kind: EdgeKind
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+  pub start: usize,
+  // first address past the last instruction of the block
+  pub end: usize,
+  pub instructions: Vec<Instruction>,
+  // the block ends in a jump whose target is only known at runtime
+  pub indirect: bool
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+  pub blocks: Vec<Block>,
+  pub edges: Vec<Edge>
+}
+
+// addresses that start a block: the entry point, jump targets, whatever follows a jump and any
+// instruction nothing falls into (return addresses and code after a halt)
+fn find_leaders(program: &[i64], starts: &HashSet<usize>) -> BTreeSet<usize> {
+  let mut leaders = BTreeSet::new();
+  let mut fallen_into = HashSet::new();
+  leaders.insert(0);
+
+  for address in starts.iter() {
+    let instruction = Instruction::decode(program, *address).unwrap();
+    let next = address + instruction.size();
+
+    if instruction.is_jump() {
+      leaders.insert(next);
+      if let Some(target) = instruction.jump_target() {
+        leaders.insert(target);
+      }
+    } else if instruction.falls_through() {
+      fallen_into.insert(next);
+    }
+  }
+
+  leaders.extend(starts.iter().filter(|address| !fallen_into.contains(address)));
+  leaders.retain(|address| starts.contains(address));
+
+  leaders
+}
+
+pub fn build(program: &[i64]) -> Cfg {
+  let starts = find_code(program);
+  let leaders = find_leaders(program, &starts);
+  let mut blocks = Vec::new();
+  let mut edges = Vec::new();
+
+  for start in leaders.iter() {
+    let mut instructions = Vec::new();
+    let mut address = *start;
+
+    let last = loop {
+      let instruction = Instruction::decode(program, address).unwrap();
+      address += instruction.size();
+      instructions.push(instruction.clone());
+
+      let ends_block = instruction.is_jump() || !instruction.falls_through();
+      if ends_block || !starts.contains(&address) || leaders.contains(&address) {
+        break instruction;
+      }
+    };
+
+    if last.is_jump() && !last.is_never_taken() {
+      if let Some(target) = last.jump_target().filter(|target| starts.contains(target)) {
+        edges.push(Edge { from: *start, to: target, kind: EdgeKind::Taken });
+      }
+    }
+    if last.falls_through() && starts.contains(&address) {
+      edges.push(Edge { from: *start, to: address, kind: EdgeKind::Fallthrough });
+    }
+
+    blocks.push(Block {
+      start: *start,
+      end: address,
+      indirect: last.is_jump() && !last.is_never_taken() && last.jump_target().is_none(),
+      instructions
+    });
+  }
+
+  Cfg { blocks, edges }
+}
+
+fn escape(text: &str) -> String {
+  let mut escaped = String::new();
+  for c in text.chars() {
+    match c {
+      '"' => escaped.push_str("\\\""),
+      '\\' => escaped.push_str("\\\\"),
+      '\n' => escaped.push_str("\\n"),
+      c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+      c => escaped.push(c)
+    }
+  }
+
+  escaped
+}
+
+impl Cfg {
+  pub fn block_at(&self, address: usize) -> Option<&Block> {
+    self.blocks.iter().find(|block| (block.start..block.end).contains(&address))
+  }
+
+  pub fn to_dot(&self) -> String {
+    let mut dot = String::from("digraph cfg {\n  node [shape=box, fontname=\"monospace\"];\n");
+
+    for block in self.blocks.iter() {
+      let label: String = block.instructions
+        .iter()
+        .map(|instruction| format!("{}: {}\\l", instruction.address, escape(&instruction.to_string())))
+        .collect();
+      let style = if block.indirect { ", color=red" } else { "" };
+      dot.push_str(&format!("  b{} [label=\"{}\"{}];\n", block.start, label, style));
+    }
+
+    for edge in self.edges.iter() {
+      let style = match edge.kind {
+        EdgeKind::Fallthrough => "style=dashed",
+        EdgeKind::Taken => "label=\"taken\""
+      };
+      dot.push_str(&format!("  b{} -> b{} [{}];\n", edge.from, edge.to, style));
+    }
+
+    if self.blocks.iter().any(|block| block.indirect) {
+      dot.push_str("  indirect [shape=diamond, label=\"?\", color=red];\n");
+      for block in self.blocks.iter().filter(|block| block.indirect) {
+        dot.push_str(&format!("  b{} -> indirect [style=dotted, color=red];\n", block.start));
+      }
+    }
+
+    dot.push_str("}\n");
+    dot
+  }
+
+  pub fn to_json(&self) -> String {
+    let blocks: Vec<String> = self.blocks
+      .iter()
+      .map(|block| {
+        let instructions: Vec<String> = block.instructions
+          .iter()
+          .map(|instruction| format!(
+            "{{\"address\":{},\"text\":\"{}\"}}",
+            instruction.address,
+            escape(&instruction.to_string())
+          ))
+          .collect();
+
+        format!(
+          "{{\"start\":{},\"end\":{},\"indirect\":{},\"instructions\":[{}]}}",
+          block.start,
+          block.end,
+          block.indirect,
+          instructions.join(",")
+        )
+      })
+      .collect();
+
+    let edges: Vec<String> = self.edges
+      .iter()
+      .map(|edge| format!("{{\"from\":{},\"to\":{},\"kind\":\"{}\"}}", edge.from, edge.to, edge.kind.name()))
+      .collect();
+
+    format!("{{\"blocks\":[{}],\"edges\":[{}]}}\n", blocks.join(","), edges.join(","))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+
+  // counts down from 3, printing every value
+  fn countdown() -> Vec<i64> {
+    assemble("
+      loop: out [count]
+            add [count], #-1, [count]
+            jt  [count], #loop
+            hlt
+      count: .data 3
+    ").unwrap()
+  }
+
+  #[test]
+  fn test_blocks() {
+    let cfg = build(&countdown());
+
+    let ranges: Vec<(usize, usize)> = cfg.blocks.iter().map(|block| (block.start, block.end)).collect();
+    assert_eq!(ranges, vec![(0, 9), (9, 10)]);
+    assert_eq!(cfg.edges, vec![
+      Edge { from: 0, to: 0, kind: EdgeKind::Taken },
+      Edge { from: 0, to: 9, kind: EdgeKind::Fallthrough }
+    ]);
+    assert_eq!(cfg.block_at(4).unwrap().start, 0);
+    assert!(cfg.block_at(10).is_none());
+  }
+
+  #[test]
+  fn test_jump_targets_split_blocks() {
+    // the jump lands in the middle of straight line code
+    let program = assemble("
+              jt  [flag], #second
+              out #1
+      second: out #2
+              hlt
+      flag:   .data 1
+    ").unwrap();
+    let cfg = build(&program);
+
+    let starts: Vec<usize> = cfg.blocks.iter().map(|block| block.start).collect();
+    assert_eq!(starts, vec![0, 3, 5]);
+    assert!(cfg.edges.contains(&Edge { from: 3, to: 5, kind: EdgeKind::Fallthrough }));
+    assert!(cfg.edges.contains(&Edge { from: 0, to: 5, kind: EdgeKind::Taken }));
+  }
+
+  #[test]
+  fn test_indirect_jumps() {
+    // call a subroutine at 12 with the return address 9 stored at rb+0, then return through it
+    let program = vec![
+      109, 20, 21101, 9, 0, 0, 1105, 1, 12, 104, 1, 99, 104, 2, 2105, 1, 0
+    ];
+    let cfg = build(&program);
+
+    let subroutine = cfg.block_at(12).unwrap();
+    assert!(subroutine.indirect);
+    assert_eq!(cfg.block_at(9).unwrap().start, 9);
+    assert!(!cfg.edges.iter().any(|edge| edge.from == 12));
+    assert!(cfg.to_dot().contains("b12 -> indirect"));
+  }
+
+  #[test]
+  fn test_export() {
+    let cfg = build(&countdown());
+
+    let expected_dot = "\
+digraph cfg {
+  node [shape=box, fontname=\"monospace\"];
+  b0 [label=\"0: out [10]\\l2: add [10], #-1, [10]\\l6: jt  [10], #0\\l\"];
+  b9 [label=\"9: hlt\\l\"];
+  b0 -> b0 [label=\"taken\"];
+  b0 -> b9 [style=dashed];
+}
+";
+    assert_eq!(cfg.to_dot(), expected_dot);
+
+    let expected_json = concat!(
+      "{\"blocks\":[",
+      "{\"start\":0,\"end\":9,\"indirect\":false,\"instructions\":[",
+      "{\"address\":0,\"text\":\"out [10]\"},",
+      "{\"address\":2,\"text\":\"add [10], #-1, [10]\"},",
+      "{\"address\":6,\"text\":\"jt  [10], #0\"}]},",
+      "{\"start\":9,\"end\":10,\"indirect\":false,\"instructions\":[{\"address\":9,\"text\":\"hlt\"}]}],",
+      "\"edges\":[{\"from\":0,\"to\":0,\"kind\":\"taken\"},{\"from\":0,\"to\":9,\"kind\":\"fallthrough\"}]}\n"
+    );
+    assert_eq!(cfg.to_json(), expected_json);
+  }
+}
@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::instruction::Opcode;
+use crate::trace::{TraceEvent, Tracer};
+
+// a cell that was written by one instruction and later executed as part of another
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Rewrite {
+  pub address: usize,
+  // pc of the instruction that wrote the cell
+  pub writer: usize,
+  // pc of the instruction the cell belonged to when it ran, either its opcode or an operand
+  pub executed_at: usize
+}
+
+// which cells a run executed, read and wrote, fed by `Machine::run_traced`. Custom opcodes
+// count as a single cell since their parameters are unknown to the tracer
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+  // start of every executed instruction and how often it ran
+  pub instructions: BTreeMap<usize, u64>,
+  // every cell fetched as part of an executed instruction, parameters included
+  pub code: BTreeSet<usize>,
+  pub reads: BTreeSet<usize>,
+  pub writes: BTreeSet<usize>,
+  pub rewrites: BTreeSet<Rewrite>,
+  last_writer: HashMap<usize, usize>
+}
+
+// consecutive addresses folded into inclusive ranges
+fn ranges(addresses: &BTreeSet<usize>) -> Vec<(usize, usize)> {
+  let mut ranges: Vec<(usize, usize)> = Vec::new();
+  for address in addresses.iter() {
+    match ranges.last_mut() {
+      Some(last) if last.1 + 1 == *address => last.1 = *address,
+      _ => ranges.push((*address, *address))
+    }
+  }
+
+  ranges
+}
+
+fn ranges_text(addresses: &BTreeSet<usize>) -> String {
+  if addresses.is_empty() {
+    return String::from("none");
+  }
+
+  let ranges: Vec<String> = ranges(addresses)
+    .iter()
+    .map(|(start, end)| if start == end { start.to_string() } else { format!("{}..={}", start, end) })
+    .collect();
+  ranges.join(", ")
+}
+
+fn ranges_json(addresses: &BTreeSet<usize>) -> String {
+  let ranges: Vec<String> = ranges(addresses)
+    .iter()
+    .map(|(start, end)| format!("[{},{}]", start, end))
+    .collect();
+  format!("[{}]", ranges.join(","))
+}
+
+impl Coverage {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn is_self_modifying(&self) -> bool {
+    !self.rewrites.is_empty()
+  }
+
+  // executed cells that were also read or written as data
+  pub fn shared(&self) -> BTreeSet<usize> {
+    self.code
+      .iter()
+      .filter(|address| self.reads.contains(address) || self.writes.contains(address))
+      .copied()
+      .collect()
+  }
+
+  pub fn report(&self) -> String {
+    let executed: u64 = self.instructions.values().sum();
+    let mut lines = vec![
+      format!("instructions executed: {} ({} distinct)", executed, self.instructions.len()),
+      format!("code: {}", ranges_text(&self.code)),
+      format!("data read: {}", ranges_text(&self.reads)),
+      format!("data written: {}", ranges_text(&self.writes)),
+      format!("code accessed as data: {}", ranges_text(&self.shared()))
+    ];
+
+    if self.is_self_modifying() {
+      lines.push(format!("self-modifying code: {} rewritten cells executed", self.rewrites.len()));
+      for rewrite in self.rewrites.iter() {
+        lines.push(format!(
+          "  !! [{}] written at {}, executed by the instruction at {}",
+          rewrite.address,
+          rewrite.writer,
+          rewrite.executed_at
+        ));
+      }
+    } else {
+      lines.push(String::from("self-modifying code: none"));
+    }
+
+    lines.join("\n")
+  }
+
+  pub fn to_json(&self) -> String {
+    let instructions: Vec<String> = self.instructions
+      .iter()
+      .map(|(address, hits)| format!("{{\"address\":{},\"hits\":{}}}", address, hits))
+      .collect();
+    let rewrites: Vec<String> = self.rewrites
+      .iter()
+      .map(|rewrite| format!(
+        "{{\"address\":{},\"writer\":{},\"executed_at\":{}}}",
+        rewrite.address,
+        rewrite.writer,
+        rewrite.executed_at
+      ))
+      .collect();
+
+    format!(
+      "{{\"instructions\":[{}],\"code\":{},\"read\":{},\"written\":{},\"self_modifying\":[{}]}}\n",
+      instructions.join(","),
+      ranges_json(&self.code),
+      ranges_json(&self.reads),
+      ranges_json(&self.writes),
+      rewrites.join(",")
+    )
+  }
+}
+
+impl Tracer for Coverage {
+  fn trace(&mut self, event: &TraceEvent) {
+    let size = Opcode::from_code(event.opcode()).map(|opcode| opcode.param_count() + 1).unwrap_or(1);
+
+    // checked before this instruction's own write, which only counts once it runs again
+    for address in event.pc..event.pc + size {
+      self.code.insert(address);
+      if let Some(writer) = self.last_writer.get(&address) {
+        self.rewrites.insert(Rewrite { address, writer: *writer, executed_at: event.pc });
+      }
+    }
+
+    *self.instructions.entry(event.pc).or_insert(0) += 1;
+    self.reads.extend(event.reads.iter());
+    if let Some((address, _)) = event.write {
+      self.writes.insert(address);
+      self.last_writer.insert(address, event.pc);
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+  use crate::hooks::Device;
+  use crate::loader::parse;
+  use crate::machine::Machine;
+
+  fn cover(program: Vec<i64>, input: Vec<i64>) -> Coverage {
+    let mut coverage = Coverage::new();
+    Machine::new(program, input).run_traced(&mut coverage).unwrap();
+
+    coverage
+  }
+
+  #[test]
+  fn test_coverage() {
+    // counts [10] down from 3, printing each value
+    let coverage = cover(vec![4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3], Vec::new());
+
+    assert_eq!(coverage.instructions.values().sum::<u64>(), 10);
+    assert_eq!(coverage.instructions[&0], 3);
+    assert_eq!(ranges(&coverage.code), vec![(0, 9)]);
+    assert_eq!(ranges(&coverage.reads), vec![(10, 10)]);
+    assert!(!coverage.is_self_modifying());
+    assert_eq!(coverage.report(), "\
+instructions executed: 10 (4 distinct)
+code: 0..=9
+data read: 10
+data written: 10
+code accessed as data: none
+self-modifying code: none");
+  }
+
+  #[test]
+  fn test_device_reads() {
+    // counts every read it answers
+    struct Counter(i64);
+
+    impl Device for Counter {
+      fn read(&mut self, _address: usize) -> i64 {
+        self.0 += 1;
+        self.0
+      }
+
+      fn write(&mut self, _address: usize, _value: i64) {}
+    }
+
+    // reads the device once through the relative base
+    let mut machine = Machine::new(vec![109, 500, 204, 0, 99], Vec::new());
+    let counter = machine.map_device(500..501, Counter(0)).unwrap();
+    let mut coverage = Coverage::new();
+
+    assert_eq!(machine.run_traced(&mut coverage).unwrap(), vec![1]);
+    assert_eq!(counter.lock().unwrap().0, 1);
+    assert_eq!(ranges(&coverage.reads), vec![(500, 500)]);
+  }
+
+  #[test]
+  fn test_rewritten_operand() {
+    // day 5 example storing its input into the first operand of the comparison
+    let coverage = cover(parse("3,3,1107,-1,8,3,4,3,99").unwrap(), vec![3]);
+
+    assert_eq!(coverage.rewrites.iter().cloned().collect::<Vec<Rewrite>>(), vec![
+      Rewrite { address: 3, writer: 0, executed_at: 2 }
+    ]);
+    assert_eq!(coverage.shared().into_iter().collect::<Vec<usize>>(), vec![3]);
+    assert_eq!(coverage.to_json(), "{\"instructions\":[\
+      {\"address\":0,\"hits\":1},{\"address\":2,\"hits\":1},{\"address\":6,\"hits\":1},{\"address\":8,\"hits\":1}],\
+      \"code\":[[0,8]],\"read\":[[3,3]],\"written\":[[3,3]],\
+      \"self_modifying\":[{\"address\":3,\"writer\":0,\"executed_at\":2}]}\n");
+  }
+
+  #[test]
+  fn test_rewritten_opcode() {
+    // runs the instruction at op as an add, then rewrites it into a mul and runs it again
+    let coverage = cover(assemble("
+      op:     add [value], #2, [value]
+              out [value]
+              jt  [done], #end
+              add #1002, #0, [op]
+              add #1, #0, [done]
+              jt  #1, #op
+      end:    hlt
+      value:  .data 3
+      done:   .data 0
+    ").unwrap(), Vec::new());
+
+    assert!(coverage.is_self_modifying());
+    assert_eq!(coverage.rewrites.iter().next(), Some(&Rewrite { address: 0, writer: 9, executed_at: 0 }));
+    assert!(coverage.report().ends_with("\
+self-modifying code: 1 rewritten cells executed
+  !! [0] written at 9, executed by the instruction at 0"));
+  }
+}
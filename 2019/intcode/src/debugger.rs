@@ -0,0 +1,347 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use crate::error::VmError;
+use crate::instruction::{self, Instruction, Opcode};
+use crate::machine::{Machine, State};
+
+// most lines `x` and `l` print at once
+const MAX_LINES: i64 = 1000;
+
+const HELP: &str = "\
+s [n]            step n instructions (default 1)
+c                continue until a breakpoint, watchpoint, missing input or halt
+n                continue until the next input or output
+b <pc>           set a breakpoint, db <pc> removes it
+w <address>      set a watchpoint on writes, dw <address> removes it
+i <value>...     queue input values
+x <address> [n]  print n memory cells (default 1)
+set <address> <value>
+                 patch a memory cell
+l [address] [n]  disassemble n instructions (default at pc, 5 instructions)
+r                print pc, relative base and pending input
+info             list breakpoints and watchpoints
+q                quit";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+  Stepped,
+  Breakpoint(usize),
+  Watchpoint { pc: usize, address: usize, value: i64 },
+  Input(i64),
+  Output(i64),
+  NeedsInput,
+  BudgetExceeded(usize),
+  Halted,
+  Error(VmError)
+}
+
+impl fmt::Display for Event {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Event::Stepped => write!(f, "stepped"),
+      Event::Breakpoint(pc) => write!(f, "breakpoint @ {}", pc),
+      Event::Watchpoint { pc, address, value } => write!(f, "watchpoint [{}] = {} (written by {})", address, value, pc),
+      Event::Input(value) => write!(f, "input {}", value),
+      Event::Output(value) => write!(f, "output {}", value),
+      Event::NeedsInput => write!(f, "waiting for input"),
+      Event::BudgetExceeded(pc) => write!(f, "budget exceeded @ {}", pc),
+      Event::Halted => write!(f, "halted"),
+      Event::Error(error) => write!(f, "error: {}", error)
+    }
+  }
+}
+
+pub struct Debugger {
+  pub machine: Machine,
+  pub output: Vec<i64>,
+  breakpoints: BTreeSet<usize>,
+  watchpoints: BTreeSet<usize>
+}
+
+impl Debugger {
+  pub fn new(machine: Machine) -> Self {
+    Self {
+      machine,
+      output: Vec::new(),
+      breakpoints: BTreeSet::new(),
+      watchpoints: BTreeSet::new()
+    }
+  }
+
+  pub fn add_breakpoint(&mut self, pc: usize) {
+    self.breakpoints.insert(pc);
+  }
+
+  pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+    self.breakpoints.remove(&pc)
+  }
+
+  pub fn add_watchpoint(&mut self, address: usize) {
+    self.watchpoints.insert(address);
+  }
+
+  pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+    self.watchpoints.remove(&address)
+  }
+
+  // executes exactly one instruction and reports what it did
+  pub fn step(&mut self) -> Event {
+    if self.machine.is_halted() {
+      return Event::Halted;
+    }
+
+    let pc = self.machine.pc;
+    let pending = self.machine.input.front().copied();
+    let is_input = self.machine.memory.read(pc)
+      .map(|word| instruction::get_opcode(word) == Opcode::Input.code())
+      .unwrap_or(false);
+
+    let state = match self.machine.step() {
+      Ok(state) => state,
+      Err(error) => return Event::Error(error)
+    };
+
+    if let Some(address) = self.machine.last_write() {
+      if self.watchpoints.contains(&address) {
+        let value = self.machine.memory.read(address).unwrap_or(0);
+        if let Some(State::Output(value)) = state {
+          self.output.push(value);
+        }
+        return Event::Watchpoint { pc, address, value };
+      }
+    }
+
+    match state {
+      Some(State::Output(value)) => {
+        self.output.push(value);
+        Event::Output(value)
+      },
+      Some(State::NeedsInput) => Event::NeedsInput,
+      Some(State::BudgetExceeded(pc)) => Event::BudgetExceeded(pc),
+      Some(State::Halted) => Event::Halted,
+      None if is_input => Event::Input(pending.unwrap_or(0)),
+      None => Event::Stepped
+    }
+  }
+
+  fn run_until(&mut self, stop_on_io: bool) -> Event {
+    let mut first = true;
+    loop {
+      // a breakpoint only stops the machine when arriving at it, not when leaving it
+      if !first && self.breakpoints.contains(&self.machine.pc) {
+        return Event::Breakpoint(self.machine.pc);
+      }
+      first = false;
+
+      match self.step() {
+        Event::Stepped => {},
+        Event::Input(_) | Event::Output(_) if !stop_on_io => {},
+        event => return event
+      }
+    }
+  }
+
+  pub fn cont(&mut self) -> Event {
+    self.run_until(false)
+  }
+
+  pub fn next_io(&mut self) -> Event {
+    self.run_until(true)
+  }
+
+  fn registers(&self) -> String {
+    let input: Vec<String> = self.machine.input.iter().map(|el| el.to_string()).collect();
+    format!(
+      "pc = {}, rb = {}, input = [{}]",
+      self.machine.pc, self.machine.relative_offset, input.join(", ")
+    )
+  }
+
+  fn memory_cells(&self, address: usize, count: usize) -> String {
+    (address..address + count)
+      .map(|address| format!("[{}] = {}", address, self.machine.memory.read(address).unwrap_or(0)))
+      .collect::<Vec<String>>()
+      .join("\n")
+  }
+
+  fn list(&self, address: usize, count: usize) -> String {
+    let memory = self.machine.memory.as_slice();
+    let mut lines = Vec::new();
+    let mut address = address;
+
+    for _ in 0..count {
+      let marker = if address == self.machine.pc { "=>" } else { "  " };
+      let breakpoint = if self.breakpoints.contains(&address) { "*" } else { " " };
+      match Instruction::decode(memory, address) {
+        Some(instruction) => {
+          lines.push(format!("{}{} {}: {}", marker, breakpoint, address, instruction));
+          address += instruction.size();
+        },
+        None => {
+          let value = self.machine.memory.read(address).unwrap_or(0);
+          lines.push(format!("{}{} {}: .data {}", marker, breakpoint, address, value));
+          address += 1;
+        }
+      }
+    }
+
+    lines.join("\n")
+  }
+
+  fn info(&self) -> String {
+    let breakpoints: Vec<String> = self.breakpoints.iter().map(|el| el.to_string()).collect();
+    let watchpoints: Vec<String> = self.watchpoints.iter().map(|el| el.to_string()).collect();
+    format!("breakpoints: [{}]\nwatchpoints: [{}]", breakpoints.join(", "), watchpoints.join(", "))
+  }
+
+  // runs a single debugger command line and returns the text to show for it
+  pub fn command(&mut self, line: &str) -> Result<String, String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let numbers = parts
+      .iter()
+      .skip(1)
+      .map(|part| part.parse::<i64>().map_err(|_| format!("invalid number `{}`", part)))
+      .collect::<Result<Vec<i64>, String>>()?;
+    let address = |index: usize| -> Result<usize, String> {
+      match numbers.get(index) {
+        Some(value) if *value >= 0 => Ok(*value as usize),
+        Some(value) => Err(format!("invalid address `{}`", value)),
+        None => Err(String::from("missing address"))
+      }
+    };
+    let count = |index: usize, default: i64| -> Result<usize, String> {
+      match numbers.get(index).copied().unwrap_or(default) {
+        value if value > MAX_LINES => Err(format!("count {} is over the limit of {}", value, MAX_LINES)),
+        value => Ok(value.max(1) as usize)
+      }
+    };
+
+    let text = match parts.first().copied().unwrap_or("") {
+      "s" | "step" => {
+        let count = numbers.first().copied().unwrap_or(1).max(1);
+        let mut event = Event::Stepped;
+        for _ in 0..count {
+          event = self.step();
+          if event != Event::Stepped {
+            break;
+          }
+        }
+        format!("{}\n{}", event, self.list(self.machine.pc, 1))
+      },
+      "c" | "continue" => format!("{}\n{}", self.cont(), self.list(self.machine.pc, 1)),
+      "n" | "next" => format!("{}\n{}", self.next_io(), self.list(self.machine.pc, 1)),
+      "b" | "break" => {
+        self.add_breakpoint(address(0)?);
+        self.info()
+      },
+      "db" => {
+        self.remove_breakpoint(address(0)?);
+        self.info()
+      },
+      "w" | "watch" => {
+        self.add_watchpoint(address(0)?);
+        self.info()
+      },
+      "dw" => {
+        self.remove_watchpoint(address(0)?);
+        self.info()
+      },
+      "i" | "input" => {
+        numbers.iter().for_each(|value| self.machine.add_input(*value));
+        self.registers()
+      },
+      "x" => self.memory_cells(address(0)?, count(1, 1)?),
+      "set" => {
+        let address = address(0)?;
+        let value = *numbers.get(1).ok_or("missing value")?;
+        if self.machine.memory.write(address, value).is_none() {
+          return Err(format!("address {} is past the memory limit", address));
+        }
+        self.memory_cells(address, 1)
+      },
+      "l" | "list" => {
+        let start = if numbers.is_empty() { self.machine.pc } else { address(0)? };
+        self.list(start, count(1, 5)?)
+      },
+      "r" | "rb" | "regs" => self.registers(),
+      "info" => self.info(),
+      "h" | "help" => String::from(HELP),
+      "" => String::new(),
+      command => return Err(format!("unknown command `{}`, try `help`", command))
+    };
+
+    Ok(text)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+
+  fn debugger(source: &str, input: Vec<i64>) -> Debugger {
+    Debugger::new(Machine::new(assemble(source).unwrap(), input))
+  }
+
+  #[test]
+  fn test_breakpoints() {
+    let mut debugger = debugger("
+      loop: add [count], #1, [count]
+            out [count]
+            lt  [count], #3, [flag]
+            jt  [flag], #loop
+            hlt
+      count: .data 0
+      flag:  .data 0
+    ", Vec::new());
+    debugger.add_breakpoint(4);
+
+    assert_eq!(debugger.cont(), Event::Breakpoint(4));
+    assert_eq!(debugger.output, vec![]);
+    assert_eq!(debugger.cont(), Event::Breakpoint(4));
+    assert_eq!(debugger.output, vec![1]);
+
+    debugger.remove_breakpoint(4);
+    assert_eq!(debugger.cont(), Event::Halted);
+    assert_eq!(debugger.output, vec![1, 2, 3]);
+  }
+
+  #[test]
+  fn test_watchpoints_and_io() {
+    let mut debugger = debugger("
+      in  [value]
+      mul [value], #2, [value]
+      out [value]
+      hlt
+      value: .data 0
+    ", Vec::new());
+    debugger.add_watchpoint(9);
+
+    assert_eq!(debugger.next_io(), Event::NeedsInput);
+    debugger.machine.add_input(21);
+    assert_eq!(debugger.step(), Event::Watchpoint { pc: 0, address: 9, value: 21 });
+    assert_eq!(debugger.cont(), Event::Watchpoint { pc: 2, address: 9, value: 42 });
+    assert_eq!(debugger.next_io(), Event::Output(42));
+    assert_eq!(debugger.step(), Event::Halted);
+  }
+
+  #[test]
+  fn test_commands() {
+    let mut debugger = debugger("arb #7\nin [10]\nout [10]\nhlt", Vec::new());
+
+    assert_eq!(debugger.command("s").unwrap(), "stepped\n=>  2: in  [10]");
+    assert_eq!(debugger.command("r").unwrap(), "pc = 2, rb = 7, input = []");
+    assert_eq!(debugger.command("set 10 5").unwrap(), "[10] = 5");
+    assert_eq!(debugger.command("i 8").unwrap(), "pc = 2, rb = 7, input = [8]");
+    assert_eq!(debugger.command("n").unwrap(), "input 8\n=>  4: out [10]");
+    assert_eq!(debugger.command("x 10 2").unwrap(), "[10] = 8\n[11] = 0");
+    assert_eq!(debugger.command("b 6").unwrap(), "breakpoints: [6]\nwatchpoints: []");
+    assert_eq!(debugger.command("c").unwrap(), "breakpoint @ 6\n=>* 6: hlt");
+    assert!(debugger.command("frobnicate").is_err());
+    assert!(debugger.command("x -1").is_err());
+    assert!(debugger.command("x 9223372036854775807 9223372036854775807").is_err());
+    assert!(debugger.command("x 0 100000000000").is_err());
+    assert!(debugger.command("l 0 1001").is_err());
+    assert_eq!(debugger.command("x 0 1000").unwrap().lines().count(), 1000);
+  }
+}
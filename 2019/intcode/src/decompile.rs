@@ -0,0 +1,649 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+use crate::disassemble::find_code;
+use crate::instruction::{Instruction, Opcode, Parameter, ParameterMode};
+
+// how far back from a jump the return address store of a call may sit
+const CALL_LOOKBACK: usize = 4;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Kind {
+  Line(String),
+  If { cond: String, then: Vec<Node>, otherwise: Vec<Node> },
+  While { cond: String, body: Vec<Node> },
+  DoWhile { body: Vec<Node>, cond: String },
+  Loop { body: Vec<Node> }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+  // address of the first instruction, labels for gotos hang off it
+  address: usize,
+  kind: Kind
+}
+
+struct Function {
+  entry: usize,
+  instructions: Vec<Instruction>,
+  // relative base at each instruction relative to the one at entry, None once it cannot be known
+  shifts: HashMap<usize, Option<i64>>
+}
+
+struct Decompiler<'a> {
+  program: &'a [i64],
+  starts: HashSet<usize>,
+  // cells covered by reachable instructions, writes there are self-modification
+  code: HashSet<usize>,
+  // call jump address -> callee entry
+  calls: HashMap<usize, usize>,
+  // instructions that only store a return address for a call
+  absorbed: HashSet<usize>,
+  entries: BTreeSet<usize>
+}
+
+fn function_name(entry: usize) -> String {
+  if entry == 0 {
+    String::from("main")
+  } else {
+    format!("func_{}", entry)
+  }
+}
+
+// the value a jump compares against zero, rendered as the condition under which it is taken
+fn taken(instruction: &Instruction, value: String) -> String {
+  if instruction.opcode == Opcode::JumpIfTrue {
+    value
+  } else {
+    negate(&value)
+  }
+}
+
+fn negate(cond: &str) -> String {
+  match cond.strip_prefix('!') {
+    Some(rest) => String::from(rest),
+    None if cond.contains(' ') => format!("!({})", cond),
+    None => format!("!{}", cond)
+  }
+}
+
+impl<'a> Decompiler<'a> {
+  fn new(program: &'a [i64]) -> Self {
+    let starts = find_code(program);
+    let mut sorted: Vec<usize> = starts.iter().copied().collect();
+    sorted.sort_unstable();
+    let instructions: Vec<Instruction> = sorted
+      .iter()
+      .map(|address| Instruction::decode(program, *address).unwrap())
+      .collect();
+
+    let code = instructions
+      .iter()
+      .flat_map(|instruction| instruction.address..instruction.address + instruction.size())
+      .collect();
+
+    // a call is an unconditional jump shortly after an instruction that stores the address of
+    // whatever follows the jump, which is where the callee returns to
+    let mut calls = HashMap::new();
+    let mut absorbed = HashSet::new();
+    for (index, jump) in instructions.iter().enumerate() {
+      let target = match jump.jump_target() {
+        Some(target) if jump.is_unconditional() && starts.contains(&target) => target,
+        _ => continue
+      };
+      let return_address = (jump.address + jump.size()) as i64;
+
+      let mut expected = jump.address;
+      for store in instructions[..index].iter().rev().take(CALL_LOOKBACK) {
+        if store.address + store.size() != expected || store.is_jump() {
+          break;
+        }
+        expected = store.address;
+
+        let params = &store.params;
+        let stores_return = match store.opcode {
+          Opcode::Add => is_literal(&params[0], return_address) && is_literal(&params[1], 0)
+            || is_literal(&params[0], 0) && is_literal(&params[1], return_address),
+          Opcode::Mul => is_literal(&params[0], return_address) && is_literal(&params[1], 1)
+            || is_literal(&params[0], 1) && is_literal(&params[1], return_address),
+          _ => false
+        };
+        if stores_return {
+          calls.insert(jump.address, target);
+          absorbed.insert(store.address);
+          break;
+        }
+      }
+    }
+
+    let mut entries: BTreeSet<usize> = calls.values().copied().collect();
+    entries.insert(0);
+
+    Decompiler { program, starts, code, calls, absorbed, entries }
+  }
+
+  fn instruction(&self, address: usize) -> Instruction {
+    Instruction::decode(self.program, address).unwrap()
+  }
+
+  // everything reachable from the entry without following calls or returns, with the relative
+  // base shift tracked along every path
+  fn function(&self, entry: usize) -> Function {
+    let mut shifts: HashMap<usize, Option<i64>> = HashMap::new();
+    let mut pending = vec![(entry, Some(0))];
+
+    while let Some((address, shift)) = pending.pop() {
+      if !self.starts.contains(&address) {
+        continue;
+      }
+      let merged = match shifts.get(&address) {
+        None => shift,
+        Some(known) if *known == shift => continue,
+        // paths disagree on the frame, so it stays unknown from here on
+        Some(None) => continue,
+        Some(Some(_)) => None
+      };
+      shifts.insert(address, merged);
+
+      let instruction = self.instruction(address);
+      let next = address + instruction.size();
+      let shift = match (instruction.opcode, instruction.params.first()) {
+        // a shift past the i64 range faults at runtime, the frame is unknown from there on
+        (Opcode::AdjustBase, Some(param)) if param.mode == ParameterMode::Immediate => {
+          merged.and_then(|shift| shift.checked_add(param.value))
+        },
+        (Opcode::AdjustBase, _) => None,
+        _ => merged
+      };
+
+      if self.calls.contains_key(&address) {
+        pending.push((next, shift));
+        continue;
+      }
+      if instruction.falls_through() {
+        pending.push((next, shift));
+      }
+      if instruction.is_jump() && !instruction.is_never_taken() {
+        if let Some(target) = instruction.jump_target() {
+          pending.push((target, shift));
+        }
+      }
+    }
+
+    let mut addresses: Vec<usize> = shifts.keys().copied().collect();
+    addresses.sort_unstable();
+    let instructions = addresses.iter().map(|address| self.instruction(*address)).collect();
+
+    Function { entry, instructions, shifts }
+  }
+
+  fn cell(&self, address: i64) -> String {
+    if address >= 0 && self.code.contains(&(address as usize)) {
+      format!("code[{}]", address)
+    } else {
+      format!("var_{}", address)
+    }
+  }
+
+  fn operand(&self, function: &Function, instruction: &Instruction, param: &Parameter) -> String {
+    match param.mode {
+      ParameterMode::Immediate => param.value.to_string(),
+      ParameterMode::Position => self.cell(param.value),
+      ParameterMode::Relative => match function.shifts
+        .get(&instruction.address)
+        .copied()
+        .flatten()
+        .and_then(|shift| shift.checked_add(param.value))
+      {
+        // main starts with the base at zero, so its frame is absolute memory
+        Some(address) if function.entry == 0 => self.cell(address),
+        Some(offset) => format!("frame[{}]", offset),
+        None if param.value < 0 => format!("rb[{}]", param.value),
+        None => format!("rb[+{}]", param.value)
+      }
+    }
+  }
+
+  fn statement(&self, function: &Function, instruction: &Instruction) -> String {
+    let operand = |index: usize| self.operand(function, instruction, &instruction.params[index]);
+    let literal = |index: usize, value: i64| is_literal(&instruction.params[index], value);
+
+    match instruction.opcode {
+      Opcode::Add | Opcode::Mul => {
+        let (identity, symbol) = if instruction.opcode == Opcode::Add { (0, "+") } else { (1, "*") };
+        let (a, b, target) = (operand(0), operand(1), operand(2));
+
+        if literal(1, identity) {
+          format!("{} = {};", target, a)
+        } else if literal(0, identity) {
+          format!("{} = {};", target, b)
+        } else if instruction.opcode == Opcode::Mul && literal(1, -1) {
+          format!("{} = -{};", target, a)
+        } else if target == a || target == b {
+          let other = if target == a { b } else { a };
+          match (symbol, other.strip_prefix('-')) {
+            ("+", Some(positive)) => format!("{} -= {};", target, positive),
+            _ => format!("{} {}= {};", target, symbol, other)
+          }
+        } else {
+          format!("{} = {} {} {};", target, a, symbol, b)
+        }
+      },
+      Opcode::LessThan => format!("{} = {} < {};", operand(2), operand(0), operand(1)),
+      Opcode::Equals => format!("{} = {} == {};", operand(2), operand(0), operand(1)),
+      Opcode::Input => format!("{} = input();", operand(0)),
+      Opcode::Output => format!("output({});", operand(0)),
+      Opcode::AdjustBase if instruction.params[0].mode == ParameterMode::Immediate => {
+        let value = instruction.params[0].value;
+        match value.checked_neg() {
+          Some(negated) if value < 0 => format!("// rb -= {}", negated),
+          _ => format!("// rb += {}", value)
+        }
+      },
+      Opcode::AdjustBase => format!("rb += {};", operand(0)),
+      Opcode::Halt => String::from("halt();"),
+      Opcode::JumpIfTrue | Opcode::JumpIfFalse => unreachable!("jumps are structured separately")
+    }
+  }
+
+  fn decompile(&self) -> String {
+    let mut text = String::new();
+
+    for entry in self.entries.iter() {
+      let function = self.function(*entry);
+      let mut structurer = Structurer {
+        decompiler: self,
+        function: &function,
+        gotos: BTreeSet::new()
+      };
+      let body = structurer.structure(0..function.instructions.len(), &[]);
+      let gotos = structurer.gotos;
+
+      if !text.is_empty() {
+        text.push('\n');
+      }
+      text.push_str(&format!("fn {}() {{\n", function_name(*entry)));
+      render(&body, 1, &gotos, &mut text);
+      text.push_str("}\n");
+    }
+
+    text
+  }
+}
+
+fn is_literal(param: &Parameter, value: i64) -> bool {
+  param.mode == ParameterMode::Immediate && param.value == value
+}
+
+struct Structurer<'a> {
+  decompiler: &'a Decompiler<'a>,
+  function: &'a Function,
+  gotos: BTreeSet<usize>
+}
+
+impl<'a> Structurer<'a> {
+  fn instructions(&self) -> &'a [Instruction] {
+    &self.function.instructions
+  }
+
+  // address right after the instruction at index - 1, which is where a region ending at index ends
+  fn end_address(&self, index: usize) -> usize {
+    let instructions = self.instructions();
+    match instructions.get(index) {
+      Some(instruction) => instruction.address,
+      None => instructions.last().map(|last| last.address + last.size()).unwrap_or(0)
+    }
+  }
+
+  fn index_of(&self, address: usize, range: Range<usize>) -> Option<usize> {
+    if self.end_address(range.end) == address {
+      return Some(range.end);
+    }
+    range.clone().find(|index| self.instructions()[*index].address == address)
+  }
+
+  fn condition(&self, instruction: &Instruction) -> String {
+    let value = self.decompiler.operand(self.function, instruction, &instruction.params[0]);
+    taken(instruction, value)
+  }
+
+  fn line(&self, address: usize, text: String) -> Node {
+    Node { address, kind: Kind::Line(text) }
+  }
+
+  // where a jump goes, as a statement: leaving or restarting the innermost loop, or a goto
+  fn jump_statement(&mut self, target: usize, loops: &[(usize, usize)]) -> String {
+    match loops.last() {
+      Some((_, exit)) if *exit == target => String::from("break;"),
+      Some((head, _)) if *head == target => String::from("continue;"),
+      _ => {
+        self.gotos.insert(target);
+        format!("goto L_{};", target)
+      }
+    }
+  }
+
+  // last jump in the range that goes back to the instruction at index, which makes it a loop head
+  fn loop_end(&self, index: usize, range: &Range<usize>) -> Option<usize> {
+    let head = self.instructions()[index].address;
+    (index..range.end).rev().find(|candidate| {
+      let instruction = &self.instructions()[*candidate];
+      instruction.jump_target() == Some(head)
+        && !instruction.is_never_taken()
+        && !self.decompiler.calls.contains_key(&instruction.address)
+    })
+  }
+
+  fn structure(&mut self, range: Range<usize>, loops: &[(usize, usize)]) -> Vec<Node> {
+    let instructions = self.instructions();
+    let mut nodes = Vec::new();
+    let mut index = range.start;
+
+    while index < range.end {
+      let instruction = &instructions[index];
+      let address = instruction.address;
+
+      let is_head = loops.iter().any(|(head, _)| *head == address);
+      if let (false, Some(end)) = (is_head, self.loop_end(index, &range)) {
+        let exit = self.end_address(end + 1);
+        let mut inner = loops.to_vec();
+        inner.push((address, exit));
+
+        let back = &instructions[end];
+        let kind = if !back.is_unconditional() {
+          let cond = self.condition(back);
+          Kind::DoWhile { body: self.structure(index..end, &inner), cond }
+        } else if instruction.is_jump() && !instruction.is_unconditional() && instruction.jump_target() == Some(exit) {
+          let cond = negate(&self.condition(instruction));
+          Kind::While { cond, body: self.structure(index + 1..end, &inner) }
+        } else {
+          Kind::Loop { body: self.structure(index..end, &inner) }
+        };
+
+        nodes.push(Node { address, kind });
+        index = end + 1;
+        continue;
+      }
+
+      if self.decompiler.absorbed.contains(&address) {
+        index += 1;
+        continue;
+      }
+      if let Some(callee) = self.decompiler.calls.get(&address) {
+        nodes.push(self.line(address, format!("{}();", function_name(*callee))));
+        index += 1;
+        continue;
+      }
+      if !instruction.is_jump() {
+        nodes.push(self.line(address, self.decompiler.statement(self.function, instruction)));
+        index += 1;
+        continue;
+      }
+      if instruction.is_never_taken() {
+        index += 1;
+        continue;
+      }
+
+      let target = match instruction.jump_target() {
+        Some(target) => target,
+        None => {
+          // returns go through a slot of the frame, anything else is a computed goto
+          let target = &instruction.params[1];
+          let jump = if target.mode == ParameterMode::Relative {
+            String::from("return;")
+          } else {
+            format!("goto *{};", self.decompiler.operand(self.function, instruction, target))
+          };
+          let text = if instruction.is_unconditional() {
+            jump
+          } else {
+            format!("if ({}) {}", self.condition(instruction), jump)
+          };
+          nodes.push(self.line(address, text));
+          index += 1;
+          continue;
+        }
+      };
+
+      // jumping to the instruction that comes next anyway does nothing
+      if index + 1 < range.end && target == self.end_address(index + 1) {
+        index += 1;
+        continue;
+      }
+      if instruction.is_unconditional() {
+        let text = self.jump_statement(target, loops);
+        nodes.push(self.line(address, text));
+        index += 1;
+        continue;
+      }
+
+      // a forward conditional jump skips the code that runs when it is not taken
+      let skipped = match self.index_of(target, index + 1..range.end) {
+        Some(skipped) if target > address && !loops.iter().any(|(_, exit)| *exit == target && skipped == range.end) => skipped,
+        _ => {
+          let cond = self.condition(instruction);
+          let text = self.jump_statement(target, loops);
+          nodes.push(self.line(address, format!("if ({}) {}", cond, text)));
+          index += 1;
+          continue;
+        }
+      };
+
+      let cond = negate(&self.condition(instruction));
+      let last = &instructions[skipped - 1];
+      let otherwise_end = match last.jump_target() {
+        Some(end) if skipped - 1 > index && last.is_unconditional() && end > target
+          && !self.decompiler.calls.contains_key(&last.address) => self.index_of(end, skipped..range.end),
+        _ => None
+      };
+
+      let kind = match otherwise_end {
+        Some(otherwise_end) => {
+          let then = self.structure(index + 1..skipped - 1, loops);
+          let otherwise = self.structure(skipped..otherwise_end, loops);
+          index = otherwise_end;
+          Kind::If { cond, then, otherwise }
+        },
+        None => {
+          let then = self.structure(index + 1..skipped, loops);
+          index = skipped;
+          Kind::If { cond, then, otherwise: Vec::new() }
+        }
+      };
+      nodes.push(Node { address, kind });
+    }
+
+    nodes
+  }
+}
+
+fn render(nodes: &[Node], depth: usize, gotos: &BTreeSet<usize>, text: &mut String) {
+  let indent = "  ".repeat(depth);
+
+  for node in nodes.iter() {
+    if gotos.contains(&node.address) {
+      text.push_str(&format!("{}L_{}:\n", "  ".repeat(depth - 1), node.address));
+    }
+
+    match &node.kind {
+      Kind::Line(line) => text.push_str(&format!("{}{}\n", indent, line)),
+      Kind::If { cond, then, otherwise } => {
+        text.push_str(&format!("{}if ({}) {{\n", indent, cond));
+        render(then, depth + 1, gotos, text);
+        if !otherwise.is_empty() {
+          text.push_str(&format!("{}}} else {{\n", indent));
+          render(otherwise, depth + 1, gotos, text);
+        }
+        text.push_str(&format!("{}}}\n", indent));
+      },
+      Kind::While { cond, body } => {
+        text.push_str(&format!("{}while ({}) {{\n", indent, cond));
+        render(body, depth + 1, gotos, text);
+        text.push_str(&format!("{}}}\n", indent));
+      },
+      Kind::DoWhile { body, cond } => {
+        text.push_str(&format!("{}do {{\n", indent));
+        render(body, depth + 1, gotos, text);
+        text.push_str(&format!("{}}} while ({});\n", indent, cond));
+      },
+      Kind::Loop { body } => {
+        text.push_str(&format!("{}loop {{\n", indent));
+        render(body, depth + 1, gotos, text);
+        text.push_str(&format!("{}}}\n", indent));
+      }
+    }
+  }
+}
+
+// lifts the program into C-like pseudo-code with one function per call target, structured
+// if/while blocks where the jumps allow it and gotos everywhere else
+pub fn decompile(program: &[i64]) -> String {
+  Decompiler::new(program).decompile()
+}
+
+// entry address of every function the decompiler found, with the calls made from each
+pub fn call_graph(program: &[i64]) -> BTreeMap<usize, BTreeSet<usize>> {
+  let decompiler = Decompiler::new(program);
+
+  decompiler.entries
+    .iter()
+    .map(|entry| {
+      let function = decompiler.function(*entry);
+      let callees = function.instructions
+        .iter()
+        .filter_map(|instruction| decompiler.calls.get(&instruction.address).copied())
+        .collect();
+      (*entry, callees)
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+
+  #[test]
+  fn test_if_and_while() {
+    let program = assemble("
+              in  [n]
+      loop:   jf  [n], #done
+              out [n]
+              add [n], #-1, [n]
+              jt  #1, #loop
+      done:   eq  [n], #0, [flag]
+              jf  [flag], #else
+              out #1
+              jt  #1, #end
+      else:   out #2
+      end:    hlt
+      n:      .data 0
+      flag:   .data 0
+    ").unwrap();
+
+    let expected = "\
+fn main() {
+  var_29 = input();
+  while (var_29) {
+    output(var_29);
+    var_29 -= 1;
+  }
+  var_30 = var_29 == 0;
+  if (var_30) {
+    output(1);
+  } else {
+    output(2);
+  }
+  halt();
+}
+";
+    assert_eq!(decompile(&program), expected);
+  }
+
+  #[test]
+  fn test_calls_and_frames() {
+    // doubles its argument in place, the return address sits in frame[0] and the argument in frame[1]
+    let program = assemble("
+              arb #100
+              in  [x]
+              add #back, #0, rb
+              add [x], #0, rb+1
+              jt  #1, #double
+      back:   out rb+1
+              hlt
+      double: arb #2
+              mul rb-1, #2, rb-1
+              arb #-2
+              jt  #1, rb
+      x:      .data 0
+    ").unwrap();
+
+    let expected = "\
+fn main() {
+  // rb += 100
+  var_29 = input();
+  var_101 = var_29;
+  func_18();
+  output(var_101);
+  halt();
+}
+
+fn func_18() {
+  // rb += 2
+  frame[1] *= 2;
+  // rb -= 2
+  return;
+}
+";
+    assert_eq!(decompile(&program), expected);
+    assert_eq!(call_graph(&program)[&0], vec![18].into_iter().collect());
+  }
+
+  #[test]
+  fn test_do_while_and_gotos() {
+    let program = assemble("
+      again:  in  [n]
+              jt  [n], #skip
+              out #0
+              jt  #1, #fail
+      skip:   out [n]
+              lt  [n], #10, [small]
+              jt  [small], #again
+              hlt
+      fail:   out #-1
+              jt  #1, #skip
+      n:      .data 0
+      small:  .data 0
+    ").unwrap();
+
+    let text = decompile(&program);
+    assert!(text.contains("do {\n"), "{}", text);
+    assert!(text.contains("} while (var_26);"), "{}", text);
+    assert!(text.contains("goto L_10;"), "{}", text);
+    assert!(text.contains("goto L_20;"), "{}", text);
+    assert!(text.contains("L_20:\n"), "{}", text);
+  }
+
+  #[test]
+  fn test_self_modification() {
+    let program = assemble("
+              add #1102, #0, [patch]
+      patch:  add #6, #7, [result]
+              out [result]
+              hlt
+      result: .data 0
+    ").unwrap();
+
+    assert!(decompile(&program).contains("code[4] = 1102;"));
+  }
+
+  #[test]
+  fn test_overflowing_frames() {
+    // arb #i64::MIN, and relative operands past the end of a huge frame
+    let program = [1001, 109, 109, 22201, 109, 4, 109, 6, 1, 209, 30, 109, 109, 109, 3, -1, 109, i64::MIN];
+    assert!(decompile(&program).contains("// rb += -9223372036854775808"));
+
+    let text = decompile(&[109, i64::MAX, 22201, 1, 2, 3, 99]);
+    assert!(text.contains("// rb += 9223372036854775807"), "{}", text);
+    assert!(text.contains("rb[+3] = rb[+1] + rb[+2];"), "{}", text);
+  }
+}
@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+use crate::instruction::{Instruction, ParameterMode};
+
+// longest run of data cells printed on a single listing line
+const DATA_PER_LINE: usize = 8;
+// column the raw words comment starts at
+const COMMENT_COLUMN: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Line {
+  Code(Instruction),
+  Data { address: usize, values: Vec<i64> }
+}
+
+impl Line {
+  pub fn address(&self) -> usize {
+    match self {
+      Line::Code(instruction) => instruction.address,
+      Line::Data { address, .. } => *address
+    }
+  }
+}
+
+// walks every path reachable from address 0 and returns the addresses that start an instruction
+pub fn find_code(program: &[i64]) -> HashSet<usize> {
+  let mut starts = HashSet::new();
+  let mut pending = vec![0];
+  let mut indirect = false;
+
+  loop {
+    while let Some(address) = pending.pop() {
+      if starts.contains(&address) {
+        continue;
+      }
+
+      let instruction = match Instruction::decode(program, address) {
+        Some(instruction) => instruction,
+        None => continue
+      };
+      starts.insert(address);
+
+      if instruction.falls_through() {
+        pending.push(address + instruction.size());
+      }
+      if instruction.is_jump() && !instruction.is_never_taken() {
+        match instruction.jump_target() {
+          Some(target) => pending.push(target),
+          None => indirect = true
+        }
+      }
+    }
+
+    if !indirect {
+      break;
+    }
+
+    // indirect jumps (usually returns through rb) hide their targets, so any immediate that points
+    // at a decodable cell inside the program is treated as a possible return address
+    let mut candidates: Vec<usize> = starts
+      .iter()
+      .filter_map(|address| Instruction::decode(program, *address))
+      .flat_map(|instruction| instruction.params)
+      .filter(|param| param.mode == ParameterMode::Immediate)
+      .filter(|param| param.value > 0 && (param.value as usize) < program.len())
+      .map(|param| param.value as usize)
+      .filter(|address| !starts.contains(address))
+      .filter(|address| Instruction::decode(program, *address).is_some())
+      .collect();
+
+    if candidates.is_empty() {
+      break;
+    }
+    candidates.sort_unstable();
+    candidates.dedup();
+    pending = candidates;
+  }
+
+  starts
+}
+
+pub fn disassemble(program: &[i64]) -> Vec<Line> {
+  let starts = find_code(program);
+  let mut lines = Vec::new();
+  let mut address = 0;
+
+  while address < program.len() {
+    if starts.contains(&address) {
+      let instruction = Instruction::decode(program, address).unwrap();
+      address += instruction.size();
+      lines.push(Line::Code(instruction));
+      continue;
+    }
+
+    let start = address;
+    while address < program.len() && !starts.contains(&address) && address - start < DATA_PER_LINE {
+      address += 1;
+    }
+    lines.push(Line::Data {
+      address: start,
+      values: program[start..address].to_vec()
+    });
+  }
+
+  lines
+}
+
+pub fn listing(program: &[i64]) -> String {
+  let width = program.len().saturating_sub(1).to_string().len();
+  let mut text = String::new();
+
+  for line in disassemble(program) {
+    let (body, raw) = match &line {
+      Line::Code(instruction) => {
+        let end = instruction.address + instruction.size();
+        let raw: Vec<String> = program[instruction.address..end].iter().map(|el| el.to_string()).collect();
+        (instruction.to_string(), raw.join(","))
+      },
+      Line::Data { values, .. } => {
+        let values: Vec<String> = values.iter().map(|el| el.to_string()).collect();
+        (format!(".data {}", values.join(", ")), String::from("unreachable"))
+      }
+    };
+
+    let prefix = format!("{:>width$}: {}", line.address(), body, width = width);
+    text.push_str(&format!("{:<column$} ; {}\n", prefix, raw, column = COMMENT_COLUMN));
+  }
+
+  text
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_listing() {
+    let program = vec![1002, 4, 3, 4, 99, 7, 8];
+
+    let expected = "\
+0: mul [4], #3, [4]              ; 1002,4,3,4
+4: hlt                           ; 99
+5: .data 7, 8                    ; unreachable
+";
+    assert_eq!(listing(&program), expected);
+  }
+
+  #[test]
+  fn test_jumps_are_followed() {
+    // jumps over a data cell that would decode as an add
+    let program = vec![1105, 1, 4, 1, 104, 0, 99];
+    let lines = disassemble(&program);
+
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[1], Line::Data { address: 3, values: vec![1] });
+    assert_eq!(lines[2].address(), 4);
+  }
+
+  #[test]
+  fn test_return_addresses() {
+    // call a subroutine at 12 with the return address 9 stored at rb+0, then return through it
+    let program = vec![
+      109, 20, 21101, 9, 0, 0, 1105, 1, 12, 104, 1, 99, 104, 2, 2105, 1, 0
+    ];
+    let starts = find_code(&program);
+
+    assert!(starts.contains(&12));
+    assert!(starts.contains(&9));
+    assert!(starts.contains(&11));
+  }
+}
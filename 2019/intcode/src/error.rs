@@ -0,0 +1,52 @@
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+  InvalidOpcode,
+  InvalidParameterMode(i64),
+  ImmediateWrite,
+  NegativeAddress(i64),
+  AddressOutOfRange(usize),
+  // an add, mul or relative base update that does not fit in an i64
+  Overflow,
+  // a custom opcode handler asked for a parameter its opcode does not have
+  NoSuchOperand,
+  Halted
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VmError {
+  pub pc: usize,
+  pub instruction: i64,
+  // 1-based index of the parameter that failed, if the failure came from an operand
+  pub operand: Option<usize>,
+  pub kind: ErrorKind
+}
+
+impl fmt::Display for ErrorKind {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      ErrorKind::InvalidOpcode => write!(f, "invalid opcode"),
+      ErrorKind::InvalidParameterMode(mode) => write!(f, "invalid parameter mode {}", mode),
+      ErrorKind::ImmediateWrite => write!(f, "write target in immediate mode"),
+      ErrorKind::NegativeAddress(address) => write!(f, "negative address {}", address),
+      ErrorKind::AddressOutOfRange(address) => write!(f, "address {} out of range", address),
+      ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+      ErrorKind::NoSuchOperand => write!(f, "no such operand"),
+      ErrorKind::Halted => write!(f, "cannot run a halted machine")
+    }
+  }
+}
+
+impl fmt::Display for VmError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{} @ {} (instruction {}", self.kind, self.pc, self.instruction)?;
+    if let Some(operand) = self.operand {
+      write!(f, ", operand {}", operand)?;
+    }
+    write!(f, ")")
+  }
+}
+
+impl Error for VmError {}
@@ -0,0 +1,421 @@
+// differential fuzzing: random programs, now and then with a faulting instruction, run through
+// Machine and a deliberately naive reference interpreter, which have to agree on output, memory
+// and how and where the run ended
+
+use crate::error::ErrorKind;
+use crate::machine::{Machine, State};
+
+// data cells live at a fixed address well past any generated code
+const DATA: usize = 1000;
+const DATA_LEN: usize = 16;
+// loop counters sit right after the data so random writes never touch them
+const COUNTERS: usize = DATA + DATA_LEN;
+const STATEMENTS: usize = 12;
+const FUEL: u64 = 100_000;
+const PROGRAMS: u64 = 500;
+
+struct XorShift(u64);
+
+impl XorShift {
+  fn new(seed: u64) -> Self {
+    // zero is a fixed point of xorshift
+    XorShift(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+  }
+
+  fn next(&mut self) -> u64 {
+    let mut x = self.0;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    self.0 = x;
+    x
+  }
+
+  fn below(&mut self, n: usize) -> usize {
+    (self.next() % n as u64) as usize
+  }
+
+  fn range(&mut self, low: i64, high: i64) -> i64 {
+    low + (self.next() % (high - low + 1) as u64) as i64
+  }
+}
+
+struct Generator {
+  rng: XorShift,
+  code: Vec<i64>,
+  // relative base at the current point of the code, known statically since arb only shows up
+  // in balanced pairs around straight line code
+  rb: i64,
+  loops: usize,
+  // (word cell, target cell, new opcode) of patches waiting for the next arithmetic instruction
+  patches: Vec<(usize, usize, i64)>
+}
+
+impl Generator {
+  fn emit(&mut self, opcode: i64, params: &[(i64, i64)]) -> usize {
+    let address = self.code.len();
+    let modes: i64 = params
+      .iter()
+      .enumerate()
+      .map(|(index, (mode, _))| mode * 10_i64.pow(index as u32 + 2))
+      .sum();
+
+    self.code.push(modes + opcode);
+    self.code.extend(params.iter().map(|(_, value)| *value));
+    address
+  }
+
+  fn data_cell(&mut self) -> i64 {
+    (DATA + self.rng.below(DATA_LEN)) as i64
+  }
+
+  fn source(&mut self) -> (i64, i64) {
+    match self.rng.below(3) {
+      0 => (0, self.data_cell()),
+      1 => (1, self.rng.range(-20, 20)),
+      _ => (2, self.data_cell() - self.rb)
+    }
+  }
+
+  fn target(&mut self) -> (i64, i64) {
+    match self.rng.below(2) {
+      0 => (0, self.data_cell()),
+      _ => (2, self.data_cell() - self.rb)
+    }
+  }
+
+  fn simple(&mut self) {
+    match self.rng.below(10) {
+      0..=5 => {
+        let opcode = [1, 2, 7, 8][self.rng.below(4)];
+        let params = [self.source(), self.source(), self.target()];
+        let address = self.emit(opcode, &params);
+
+        // self-modification: rewrite this instruction into another arithmetic one of the same shape
+        for (word_cell, target_cell, new_opcode) in self.patches.drain(..) {
+          self.code[word_cell] = self.code[address] / 100 * 100 + new_opcode;
+          self.code[target_cell] = address as i64;
+        }
+      },
+      6 => {
+        let param = self.target();
+        self.emit(3, &[param]);
+      },
+      7 | 8 => {
+        let param = self.source();
+        self.emit(4, &[param]);
+      },
+      _ => {
+        let address = self.emit(1, &[(1, 0), (1, 0), (0, 0)]);
+        let new_opcode = [1, 2, 7, 8][self.rng.below(4)];
+        self.patches.push((address + 1, address + 3, new_opcode));
+      }
+    }
+  }
+
+  // forward jump over a couple of simple statements
+  fn skip(&mut self) {
+    let opcode = 5 + self.rng.below(2) as i64;
+    let condition = self.source();
+    let address = self.emit(opcode, &[condition, (1, 0)]);
+    for _ in 0..=self.rng.below(2) {
+      self.simple();
+    }
+    self.code[address + 2] = self.code.len() as i64;
+  }
+
+  // an instruction the machine has to reject
+  fn fault(&mut self) {
+    match self.rng.below(4) {
+      0 => self.code.push(42),
+      1 => {
+        let (factor, target) = (self.rng.range(2, 9), self.target());
+        self.emit(2, &[(1, 1 << 62), (1, factor), target]);
+      },
+      2 => {
+        let address = -1 - self.rng.range(0, 5);
+        self.emit(4, &[(0, address)]);
+      },
+      _ => {
+        let params = [self.source(), self.source(), self.target()];
+        let address = self.emit(1, &params);
+        // pushes the write mode digit past anything valid
+        self.code[address] += 30000;
+      }
+    }
+  }
+
+  fn statement(&mut self) {
+    if self.rng.below(50) == 0 {
+      self.fault();
+      return;
+    }
+
+    match self.rng.below(10) {
+      0..=5 => self.simple(),
+      6 => self.skip(),
+      7 => {
+        let shift = self.rng.range(-8, 8);
+        self.emit(9, &[(1, shift)]);
+        self.rb += shift;
+        for _ in 0..=self.rng.below(3) {
+          self.simple();
+        }
+        self.emit(9, &[(1, -shift)]);
+        self.rb -= shift;
+      },
+      _ => {
+        // bounded loop on a private counter
+        let counter = (COUNTERS + self.loops) as i64;
+        self.loops += 1;
+        let iterations = self.rng.range(1, 4);
+        self.emit(1, &[(1, iterations), (1, 0), (0, counter)]);
+
+        let start = self.code.len() as i64;
+        for _ in 0..=self.rng.below(3) {
+          if self.rng.below(4) == 0 {
+            self.skip();
+          } else {
+            self.simple();
+          }
+        }
+        self.emit(1, &[(0, counter), (1, -1), (0, counter)]);
+        self.emit(5, &[(0, counter), (1, start)]);
+      }
+    }
+  }
+}
+
+// a program plus the input it gets
+fn generate(seed: u64) -> (Vec<i64>, Vec<i64>) {
+  let mut generator = Generator {
+    rng: XorShift::new(seed),
+    code: Vec::new(),
+    rb: DATA as i64,
+    loops: 0,
+    patches: Vec::new()
+  };
+
+  generator.emit(9, &[(1, DATA as i64)]);
+  for _ in 0..STATEMENTS {
+    generator.statement();
+  }
+  generator.emit(99, &[]);
+
+  // patches nobody claimed rewrite a data cell instead
+  for (word_cell, target_cell, _) in std::mem::take(&mut generator.patches) {
+    generator.code[word_cell] = 7;
+    generator.code[target_cell] = DATA as i64;
+  }
+
+  let mut program = std::mem::take(&mut generator.code);
+  assert!(program.len() < DATA);
+  program.resize(DATA, 0);
+  for _ in 0..DATA_LEN {
+    program.push(generator.rng.range(-50, 50));
+  }
+
+  let input = (0..generator.rng.below(6)).map(|_| generator.rng.range(-20, 20)).collect();
+  (program, input)
+}
+
+#[derive(Debug, PartialEq)]
+enum End {
+  Halted,
+  NeedsInput,
+  OutOfFuel,
+  Overflow,
+  Fault
+}
+
+#[derive(Debug)]
+struct Reference {
+  memory: Vec<i64>,
+  pc: usize,
+  rb: i64,
+  output: Vec<i64>,
+  end: End
+}
+
+// the spec as literally as possible: decode digits on every step, grow memory on every write
+fn reference(program: &[i64], input: &[i64]) -> Reference {
+  let mut memory = program.to_vec();
+  let (mut pc, mut rb, mut executed) = (0_usize, 0_i64, 0_u64);
+  let mut input = input.iter();
+  let mut output = Vec::new();
+
+  let fetch = |memory: &Vec<i64>, address: usize| memory.get(address).copied().unwrap_or(0);
+
+  let end = loop {
+    if executed >= FUEL {
+      break End::OutOfFuel;
+    }
+
+    let word = fetch(&memory, pc);
+    let mode = |n: u32| (word / 10_i64.pow(n + 1)) % 10;
+    let address = |memory: &Vec<i64>, n: u32| -> Option<usize> {
+      let cell = fetch(memory, pc + n as usize);
+      let address = match mode(n) {
+        0 => cell,
+        1 => return Some(pc + n as usize),
+        2 => cell + rb,
+        _ => return None
+      };
+      if address < 0 {
+        None
+      } else {
+        Some(address as usize)
+      }
+    };
+    let value = |memory: &Vec<i64>, n: u32| address(memory, n).map(|address| fetch(memory, address));
+    let store = |memory: &mut Vec<i64>, address: usize, value: i64| {
+      if address >= memory.len() {
+        memory.resize(address + 1, 0);
+      }
+      memory[address] = value;
+    };
+
+    match word % 100 {
+      opcode @ (1 | 2 | 7 | 8) => {
+        let (a, b, target) = match (value(&memory, 1), value(&memory, 2), address(&memory, 3)) {
+          (Some(a), Some(b), Some(target)) if mode(3) != 1 => (a, b, target),
+          _ => break End::Fault
+        };
+        let result = match opcode {
+          1 => a.checked_add(b),
+          2 => a.checked_mul(b),
+          7 => Some((a < b) as i64),
+          _ => Some((a == b) as i64)
+        };
+        match result {
+          Some(result) => store(&mut memory, target, result),
+          None => break End::Overflow
+        }
+        pc += 4;
+      },
+      3 => {
+        let target = match address(&memory, 1) {
+          Some(target) if mode(1) != 1 => target,
+          _ => break End::Fault
+        };
+        match input.next() {
+          Some(value) => store(&mut memory, target, *value),
+          None => break End::NeedsInput
+        }
+        pc += 2;
+      },
+      4 => {
+        match value(&memory, 1) {
+          Some(value) => output.push(value),
+          None => break End::Fault
+        }
+        pc += 2;
+      },
+      opcode @ (5 | 6) => {
+        let (condition, target) = match (value(&memory, 1), value(&memory, 2)) {
+          (Some(condition), Some(target)) if target >= 0 => (condition, target),
+          _ => break End::Fault
+        };
+        if (condition != 0) == (opcode == 5) {
+          pc = target as usize;
+        } else {
+          pc += 3;
+        }
+      },
+      9 => {
+        match value(&memory, 1) {
+          Some(value) => rb += value,
+          None => break End::Fault
+        }
+        pc += 2;
+      },
+      99 => break End::Halted,
+      _ => break End::Fault
+    }
+
+    executed += 1;
+  };
+
+  Reference { memory, pc, rb, output, end }
+}
+
+// runs through execute and its fast path, or one step at a time without it
+fn compare(seed: u64, program: &[i64], input: &[i64], expected: &Reference, stepped: bool) {
+  let mut machine = Machine::new(program.to_vec(), input.to_vec());
+  machine.set_budget(Some(FUEL));
+
+  let mut output = Vec::new();
+  let state = loop {
+    let state = if stepped {
+      match machine.step() {
+        Ok(None) => continue,
+        Ok(Some(state)) => Ok(state),
+        Err(error) => Err(error)
+      }
+    } else {
+      machine.execute()
+    };
+    match state {
+      Ok(State::Output(value)) => output.push(value),
+      other => break other
+    }
+  };
+
+  let context = format!("seed {} (stepped {}): {:?} with input {:?}", seed, stepped, program, input);
+  let end = match state {
+    Ok(State::Halted) => End::Halted,
+    Ok(State::NeedsInput) => End::NeedsInput,
+    Ok(State::BudgetExceeded(_)) => End::OutOfFuel,
+    Ok(State::Output(_)) => unreachable!(),
+    Err(error) => {
+      // the machine reports where it failed, which has to be the instruction the reference gave up on
+      assert_eq!(error.pc, expected.pc, "{}", context);
+      if error.kind == ErrorKind::Overflow {
+        End::Overflow
+      } else {
+        End::Fault
+      }
+    }
+  };
+
+  assert_eq!(end, expected.end, "{}", context);
+  assert_eq!(output, expected.output, "{}", context);
+  assert_eq!(machine.pc, expected.pc, "{}", context);
+  assert_eq!(machine.relative_offset, expected.rb, "{}", context);
+  assert_eq!(machine.memory.as_slice(), &expected.memory[..], "{}", context);
+}
+
+#[test]
+fn test_reference() {
+  // day 5 comparison program, day 9 quine
+  let program = crate::loader::parse("3,9,8,9,10,9,4,9,99,-1,8").unwrap();
+  assert_eq!(reference(&program, &[8]).output, vec![1]);
+  assert_eq!(reference(&program, &[]).end, End::NeedsInput);
+
+  let quine = crate::loader::parse("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99").unwrap();
+  let result = reference(&quine, &[]);
+  assert_eq!((result.output, result.end), (quine, End::Halted));
+
+  assert_eq!(reference(&[1105, 1, 0], &[]).end, End::OutOfFuel);
+  assert_eq!(reference(&[1102, 1 << 62, 4, 0, 99], &[]).end, End::Overflow);
+}
+
+#[test]
+fn test_differential() {
+  let mut ends = Vec::new();
+
+  for seed in 1..=PROGRAMS {
+    let (program, input) = generate(seed);
+    let expected = reference(&program, &input);
+    compare(seed, &program, &input, &expected, true);
+    compare(seed, &program, &input, &expected, false);
+    ends.push(expected.end);
+  }
+
+  // the generator has to keep producing interesting programs for this to mean anything
+  let count = |end: End| ends.iter().filter(|el| **el == end).count();
+  assert!(count(End::Halted) > PROGRAMS as usize / 4);
+  assert!(count(End::NeedsInput) > 0);
+  assert!(count(End::Fault) > 0);
+  assert!(count(End::Overflow) > 0);
+}
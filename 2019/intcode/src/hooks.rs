@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use crate::error::{ErrorKind, VmError};
+use crate::machine::{Machine, State};
+
+// a virtual device answering reads and writes for the address range it is mapped to
+pub trait Device: Send {
+  fn read(&mut self, address: usize) -> i64;
+  fn write(&mut self, address: usize, value: i64);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookError {
+  // built-in or outside 0..100, the range an instruction word can encode
+  OpcodeTaken(i64),
+  // more parameters than an instruction word has mode digits for
+  TooManyParams(usize),
+  // the range shares addresses with a device mapped earlier
+  Overlap(Range<usize>)
+}
+
+impl fmt::Display for HookError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      HookError::OpcodeTaken(code) => write!(f, "opcode {} is not free", code),
+      HookError::TooManyParams(params) => write!(f, "{} parameters do not fit in an instruction", params),
+      HookError::Overlap(range) => write!(f, "{:?} overlaps a mapped device", range)
+    }
+  }
+}
+
+impl Error for HookError {}
+
+pub type SharedDevice = Arc<Mutex<dyn Device>>;
+
+pub type OpcodeHandler = Arc<dyn Fn(&mut OpcodeContext) -> Result<Option<State>, VmError> + Send + Sync>;
+
+#[derive(Clone)]
+pub(crate) struct CustomOpcode {
+  pub(crate) params: usize,
+  pub(crate) handler: OpcodeHandler
+}
+
+// what a custom opcode handler gets to work with, parameters are 1-based like in VmError
+pub struct OpcodeContext<'a> {
+  pub machine: &'a mut Machine,
+  pub(crate) params: usize,
+  pub(crate) jumped: bool
+}
+
+impl<'a> OpcodeContext<'a> {
+  fn check(&self, operand: usize) -> Result<(), VmError> {
+    if operand == 0 || operand > self.params {
+      return Err(self.machine.error(Some(operand), ErrorKind::NoSuchOperand));
+    }
+
+    Ok(())
+  }
+
+  // value of a parameter, honouring its mode
+  pub fn param(&self, operand: usize) -> Result<i64, VmError> {
+    self.check(operand)?;
+    self.machine.get_param_value(operand)
+  }
+
+  // stores through a parameter the way the built-in instructions write their results
+  pub fn set_param(&mut self, operand: usize, value: i64) -> Result<(), VmError> {
+    self.check(operand)?;
+    self.machine.write(operand, value)
+  }
+
+  // continues at the target instead of the next instruction
+  pub fn jump(&mut self, target: i64) -> Result<(), VmError> {
+    self.machine.jump(target)?;
+    self.jumped = true;
+
+    Ok(())
+  }
+}
+
+// extra opcodes and mapped devices, cloning a machine shares its devices with the clone
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+  pub(crate) opcodes: HashMap<i64, CustomOpcode>,
+  pub(crate) devices: Vec<(Range<usize>, SharedDevice)>
+}
+
+impl fmt::Debug for Hooks {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut opcodes: Vec<&i64> = self.opcodes.keys().collect();
+    opcodes.sort_unstable();
+    let devices: Vec<&Range<usize>> = self.devices.iter().map(|(range, _)| range).collect();
+
+    f.debug_struct("Hooks")
+      .field("opcodes", &opcodes)
+      .field("devices", &devices)
+      .finish()
+  }
+}
+
+impl Hooks {
+  pub(crate) fn device(&self, address: usize) -> Option<&SharedDevice> {
+    self.devices
+      .iter()
+      .find(|(range, _)| range.contains(&address))
+      .map(|(_, device)| device)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+  use crate::trace::RingBuffer;
+
+  // counts up on every read and keeps everything written to it
+  #[derive(Default)]
+  struct Port {
+    ticks: i64,
+    written: Vec<(usize, i64)>
+  }
+
+  impl Device for Port {
+    fn read(&mut self, _address: usize) -> i64 {
+      self.ticks += 1;
+      self.ticks
+    }
+
+    fn write(&mut self, address: usize, value: i64) {
+      self.written.push((address, value));
+    }
+  }
+
+  #[test]
+  fn test_devices() {
+    let program = assemble("
+      add [1000], #0, [1001]
+      mul [1000], #10, [1002]
+      out [1000]
+      hlt
+    ").unwrap();
+    let mut machine = Machine::new(program, Vec::new());
+    let port = machine.map_device(1000..1003, Port::default()).unwrap();
+    assert_eq!(machine.map_device(1002..1010, Port::default()).err(), Some(HookError::Overlap(1002..1010)));
+
+    let mut tracer = RingBuffer::new(4);
+    assert_eq!(machine.run_traced(&mut tracer).unwrap(), vec![3]);
+    assert_eq!(port.lock().unwrap().written, vec![(1001, 1), (1002, 20)]);
+    // device writes are traced like any other
+    assert_eq!(tracer.events()[1].write, Some((1002, 20)));
+    // nothing reached the backing memory
+    assert_eq!(machine.memory.read(1001), Some(0));
+    assert_eq!(machine.memory.allocated(), 11);
+  }
+
+  #[test]
+  fn test_custom_opcodes() {
+    // 20 adds one to its parameter, 21 jumps to its first parameter when the second is negative
+    let program = vec![20, 9, 21, 8, 9, 104, 0, 99, 5, -2];
+    let mut machine = Machine::new(program, Vec::new());
+    machine.register_opcode(20, 1, |context| {
+      let value = context.param(1)?;
+      context.set_param(1, value + 1)?;
+      Ok(None)
+    }).unwrap();
+    machine.register_opcode(21, 2, |context| {
+      if context.param(2)? < 0 {
+        let target = context.param(1)?;
+        context.jump(target)?;
+      }
+      Ok(None)
+    }).unwrap();
+    assert_eq!(machine.register_opcode(9, 1, |_| Ok(None)), Err(HookError::OpcodeTaken(9)));
+    assert_eq!(machine.register_opcode(100, 1, |_| Ok(None)), Err(HookError::OpcodeTaken(100)));
+    assert_eq!(machine.register_opcode(22, 18, |_| Ok(None)), Err(HookError::TooManyParams(18)));
+
+    assert_eq!(machine.run().unwrap(), vec![0]);
+    assert_eq!(machine.memory.read(9), Some(-1));
+    assert_eq!(machine.instructions_executed(), 4);
+
+    let mut machine = Machine::new(vec![1120, 99, 42], Vec::new());
+    machine.register_opcode(20, 1, |context| Ok(Some(State::Output(context.param(1)?)))).unwrap();
+    assert_eq!(machine.execute().unwrap(), State::Output(99));
+    assert_eq!(machine.pc, 2);
+    assert_eq!(machine.execute().unwrap_err().kind, ErrorKind::InvalidOpcode);
+
+    // operands the opcode was not registered with fail instead of reading past the word
+    let mut machine = Machine::new(vec![20, 5, 99], Vec::new());
+    machine.register_opcode(20, 1, |context| Ok(Some(State::Output(context.param(18)?)))).unwrap();
+    let error = machine.execute().unwrap_err();
+    assert_eq!((error.operand, error.kind), (Some(18), ErrorKind::NoSuchOperand));
+  }
+}
@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ops::Range;
+use crate::disassemble::find_code;
+use crate::instruction::Instruction;
+use crate::memory::Memory;
+use crate::snapshot::Snapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Radix {
+  Decimal,
+  Hex
+}
+
+impl Radix {
+  fn format(self, value: i64) -> String {
+    match self {
+      Radix::Decimal => value.to_string(),
+      Radix::Hex if value < 0 => format!("-{:#x}", value.unsigned_abs()),
+      Radix::Hex => format!("{:#x}", value)
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct DumpOptions {
+  pub radix: Radix,
+  pub columns: usize,
+  // list the instructions starting on each row next to it
+  pub annotate: bool
+}
+
+impl Default for DumpOptions {
+  fn default() -> Self {
+    DumpOptions {
+      radix: Radix::Decimal,
+      columns: 8,
+      annotate: true
+    }
+  }
+}
+
+// instructions reachable from address 0, keyed by address
+fn instructions(memory: &Memory) -> BTreeMap<usize, Instruction> {
+  let program = memory.as_slice();
+  find_code(program)
+    .into_iter()
+    .filter_map(|address| Instruction::decode(program, address).map(|instruction| (address, instruction)))
+    .collect()
+}
+
+// the instruction a cell belongs to, either as its opcode or as one of its parameters
+fn owner(instructions: &BTreeMap<usize, Instruction>, address: usize) -> Option<&Instruction> {
+  instructions
+    .range(..=address)
+    .next_back()
+    .map(|(_, instruction)| instruction)
+    .filter(|instruction| address < instruction.address + instruction.size())
+}
+
+// one row per `columns` cells with the address up front. Cells past the limit show as `-`
+pub fn dump(memory: &Memory, range: Range<usize>, options: &DumpOptions) -> String {
+  let columns = options.columns.max(1);
+  let cells: Vec<String> = range
+    .clone()
+    .map(|address| {
+      memory
+        .read(address)
+        .map(|value| options.radix.format(value))
+        .unwrap_or_else(|| String::from("-"))
+    })
+    .collect();
+  let width = cells.iter().map(|cell| cell.len()).max().unwrap_or(1);
+  let address_width = range.end.saturating_sub(1).to_string().len().max(4);
+  let instructions = if options.annotate { instructions(memory) } else { BTreeMap::new() };
+
+  let mut lines = Vec::new();
+  for (row, chunk) in cells.chunks(columns).enumerate() {
+    let start = range.start + row * columns;
+    let values: Vec<String> = chunk.iter().map(|cell| format!("{:>width$}", cell, width = width)).collect();
+    let mut line = format!("{:>aw$}: {}", start, values.join(" "), aw = address_width);
+
+    let notes: Vec<String> = instructions
+      .range(start..start + chunk.len())
+      .map(|(address, instruction)| format!("{}: {}", address, instruction))
+      .collect();
+    if !notes.is_empty() {
+      let padding = (columns - chunk.len()) * (width + 1);
+      line.push_str(&format!("{} | {}", " ".repeat(padding), notes.join("; ")));
+    }
+    lines.push(line);
+  }
+
+  lines.join("\n")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CellChange {
+  pub address: usize,
+  pub before: i64,
+  pub after: i64
+}
+
+// every cell holding a different value, both sides reading unwritten cells as zero
+pub fn diff(before: &Memory, after: &Memory) -> Vec<CellChange> {
+  let mut addresses: BTreeSet<usize> = (0..before.as_slice().len().max(after.as_slice().len())).collect();
+  addresses.extend(before.paged_cells().iter().map(|(address, _)| *address));
+  addresses.extend(after.paged_cells().iter().map(|(address, _)| *address));
+
+  addresses
+    .into_iter()
+    .filter_map(|address| {
+      let (before, after) = (before.read(address).unwrap_or(0), after.read(address).unwrap_or(0));
+      if before == after {
+        None
+      } else {
+        Some(CellChange { address, before, after })
+      }
+    })
+    .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotDiff {
+  pub pc: (usize, usize),
+  pub relative_offset: (i64, i64),
+  pub halted: (bool, bool),
+  pub cells: Vec<CellChange>,
+  // instructions of the first snapshot, used to point out which ones a change patched
+  instructions: BTreeMap<usize, Instruction>
+}
+
+impl SnapshotDiff {
+  pub fn new(before: &Snapshot, after: &Snapshot) -> Self {
+    SnapshotDiff {
+      pc: (before.pc, after.pc),
+      relative_offset: (before.relative_offset, after.relative_offset),
+      halted: (before.halted, after.halted),
+      cells: diff(&before.memory, &after.memory),
+      instructions: instructions(&before.memory)
+    }
+  }
+
+  // changed cells that held code in the first snapshot
+  pub fn patched_code(&self) -> Vec<&CellChange> {
+    self.cells
+      .iter()
+      .filter(|change| owner(&self.instructions, change.address).is_some())
+      .collect()
+  }
+
+  pub fn render(&self, radix: Radix) -> String {
+    let mut lines = Vec::new();
+    if self.pc.0 != self.pc.1 {
+      lines.push(format!("pc: {} -> {}", self.pc.0, self.pc.1));
+    }
+    if self.relative_offset.0 != self.relative_offset.1 {
+      lines.push(format!("rb: {} -> {}", self.relative_offset.0, self.relative_offset.1));
+    }
+    if self.halted.0 != self.halted.1 {
+      lines.push(format!("halted: {} -> {}", self.halted.0, self.halted.1));
+    }
+
+    lines.push(format!("{} cells changed", self.cells.len()));
+    for change in self.cells.iter() {
+      let mut line = format!(
+        "{:>6}: {} -> {}",
+        change.address,
+        radix.format(change.before),
+        radix.format(change.after)
+      );
+      if let Some(instruction) = owner(&self.instructions, change.address) {
+        line.push_str(&format!("  (in {}: {})", instruction.address, instruction));
+      }
+      lines.push(line);
+    }
+
+    lines.join("\n")
+  }
+}
+
+impl fmt::Display for SnapshotDiff {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.render(Radix::Decimal))
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::loader::parse;
+  use crate::machine::Machine;
+
+  #[test]
+  fn test_dump() {
+    let memory = Memory::new(parse("1,9,10,3,2,3,11,0,99,30,40,50").unwrap());
+    let options = DumpOptions { columns: 4, ..DumpOptions::default() };
+
+    assert_eq!(dump(&memory, 0..12, &options), [
+      "   0:  1  9 10  3 | 0: add [9], [10], [3]",
+      "   4:  2  3 11  0 | 4: mul [3], [11], [0]",
+      "   8: 99 30 40 50 | 8: hlt"
+    ].join("\n"));
+
+    let options = DumpOptions { radix: Radix::Hex, columns: 5, annotate: false };
+    assert_eq!(dump(&memory, 8..12, &options), "   8: 0x63 0x1e 0x28 0x32");
+
+    let mut limited = Memory::new(vec![-255, 7]);
+    limited.set_limit(Some(2));
+    let options = DumpOptions { radix: Radix::Hex, columns: 4, annotate: true };
+    assert_eq!(dump(&limited, 0..3, &options), "   0: -0xff   0x7     -");
+  }
+
+  #[test]
+  fn test_diff() {
+    let mut after = Memory::new(vec![1, 2, 3]);
+    after.write(1, 5).unwrap();
+    after.write(5, 6).unwrap();
+    after.write(1 << 21, -1).unwrap();
+
+    assert_eq!(diff(&Memory::new(vec![1, 2, 3]), &after), vec![
+      CellChange { address: 1, before: 2, after: 5 },
+      CellChange { address: 5, before: 0, after: 6 },
+      CellChange { address: 1 << 21, before: 0, after: -1 }
+    ]);
+  }
+
+  #[test]
+  fn test_snapshot_diff() {
+    // day 2 style: patch noun and verb, then run
+    let mut machine = Machine::new(parse("1,0,0,3,2,3,11,0,99,30,40,50").unwrap(), Vec::new());
+    let before = machine.snapshot();
+    machine.memory.write(1, 9).unwrap();
+    machine.memory.write(2, 10).unwrap();
+    machine.run().unwrap();
+
+    let diff = SnapshotDiff::new(&before, &machine.snapshot());
+    assert_eq!(diff.patched_code().len(), 4);
+    assert_eq!(diff.to_string(), [
+      "pc: 0 -> 8",
+      "halted: false -> true",
+      "4 cells changed",
+      "     0: 1 -> 3500  (in 0: add [0], [0], [3])",
+      "     1: 0 -> 9  (in 0: add [0], [0], [3])",
+      "     2: 0 -> 10  (in 0: add [0], [0], [3])",
+      "     3: 3 -> 70  (in 0: add [0], [0], [3])"
+    ].join("\n"));
+  }
+}
@@ -0,0 +1,285 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParameterMode {
+  Position,
+  Immediate,
+  Relative
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Opcode {
+  Add,
+  Mul,
+  Input,
+  Output,
+  JumpIfTrue,
+  JumpIfFalse,
+  LessThan,
+  Equals,
+  AdjustBase,
+  Halt
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Parameter {
+  pub mode: ParameterMode,
+  pub value: i64
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+  pub address: usize,
+  pub opcode: Opcode,
+  pub params: Vec<Parameter>
+}
+
+// an i64 has room for 17 mode digits next to the two opcode digits
+pub const MAX_PARAMS: usize = 17;
+
+pub fn get_opcode(instruction: i64) -> i64 {
+  instruction % 100
+}
+
+// the raw mode digit is handed back when it is not one we understand, operands past the last
+// digit an i64 holds are in position mode
+pub fn get_param_mode(instruction: i64, offset: usize) -> Result<ParameterMode, i64> {
+  let digit = u32::try_from(offset)
+    .ok()
+    .and_then(|offset| offset.checked_add(1))
+    .and_then(|exponent| 10_i64.checked_pow(exponent))
+    .map(|divisor| (instruction / divisor) % 10)
+    .unwrap_or(0);
+
+  match digit {
+    2 => Ok(ParameterMode::Relative),
+    1 => Ok(ParameterMode::Immediate),
+    0 => Ok(ParameterMode::Position),
+    mode => Err(mode)
+  }
+}
+
+impl Opcode {
+  pub fn from_code(code: i64) -> Option<Opcode> {
+    match code {
+      1 => Some(Opcode::Add),
+      2 => Some(Opcode::Mul),
+      3 => Some(Opcode::Input),
+      4 => Some(Opcode::Output),
+      5 => Some(Opcode::JumpIfTrue),
+      6 => Some(Opcode::JumpIfFalse),
+      7 => Some(Opcode::LessThan),
+      8 => Some(Opcode::Equals),
+      9 => Some(Opcode::AdjustBase),
+      99 => Some(Opcode::Halt),
+      _ => None
+    }
+  }
+
+  pub fn code(self) -> i64 {
+    match self {
+      Opcode::Add => 1,
+      Opcode::Mul => 2,
+      Opcode::Input => 3,
+      Opcode::Output => 4,
+      Opcode::JumpIfTrue => 5,
+      Opcode::JumpIfFalse => 6,
+      Opcode::LessThan => 7,
+      Opcode::Equals => 8,
+      Opcode::AdjustBase => 9,
+      Opcode::Halt => 99
+    }
+  }
+
+  pub fn mnemonic(self) -> &'static str {
+    match self {
+      Opcode::Add => "add",
+      Opcode::Mul => "mul",
+      Opcode::Input => "in",
+      Opcode::Output => "out",
+      Opcode::JumpIfTrue => "jt",
+      Opcode::JumpIfFalse => "jf",
+      Opcode::LessThan => "lt",
+      Opcode::Equals => "eq",
+      Opcode::AdjustBase => "arb",
+      Opcode::Halt => "hlt"
+    }
+  }
+
+  pub fn from_mnemonic(mnemonic: &str) -> Option<Opcode> {
+    match mnemonic {
+      "add" => Some(Opcode::Add),
+      "mul" => Some(Opcode::Mul),
+      "in" => Some(Opcode::Input),
+      "out" => Some(Opcode::Output),
+      "jt" => Some(Opcode::JumpIfTrue),
+      "jf" => Some(Opcode::JumpIfFalse),
+      "lt" => Some(Opcode::LessThan),
+      "eq" => Some(Opcode::Equals),
+      "arb" => Some(Opcode::AdjustBase),
+      "hlt" => Some(Opcode::Halt),
+      _ => None
+    }
+  }
+
+  pub fn param_count(self) -> usize {
+    match self {
+      Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => 3,
+      Opcode::JumpIfTrue | Opcode::JumpIfFalse => 2,
+      Opcode::Input | Opcode::Output | Opcode::AdjustBase => 1,
+      Opcode::Halt => 0
+    }
+  }
+
+  // 1-based index of the parameter this instruction writes to
+  pub fn write_param(self) -> Option<usize> {
+    match self {
+      Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => Some(3),
+      Opcode::Input => Some(1),
+      _ => None
+    }
+  }
+}
+
+impl Instruction {
+  // None when the cell does not hold a well formed instruction
+  pub fn decode(memory: &[i64], address: usize) -> Option<Instruction> {
+    let instruction = *memory.get(address)?;
+    let opcode = Opcode::from_code(get_opcode(instruction))?;
+
+    let mut params = Vec::new();
+    for offset in 1..=opcode.param_count() {
+      let mode = get_param_mode(instruction, offset).ok()?;
+      if mode == ParameterMode::Immediate && opcode.write_param() == Some(offset) {
+        return None;
+      }
+
+      params.push(Parameter {
+        mode,
+        value: *memory.get(address + offset)?
+      });
+    }
+
+    // leftover mode digits mean this is probably data that happens to look like an opcode
+    if instruction / 10_i64.pow(opcode.param_count() as u32 + 2) != 0 {
+      return None;
+    }
+
+    Some(Instruction {
+      address,
+      opcode,
+      params
+    })
+  }
+
+  // number of cells the instruction occupies, opcode included
+  pub fn size(&self) -> usize {
+    self.params.len() + 1
+  }
+
+  // statically known jump target, only available for immediate mode targets
+  pub fn jump_target(&self) -> Option<usize> {
+    match self.opcode {
+      Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+        let target = self.params[1];
+        if target.mode == ParameterMode::Immediate && target.value >= 0 {
+          Some(target.value as usize)
+        } else {
+          None
+        }
+      },
+      _ => None
+    }
+  }
+
+  pub fn is_jump(&self) -> bool {
+    self.opcode == Opcode::JumpIfTrue || self.opcode == Opcode::JumpIfFalse
+  }
+
+  // jumps whose condition is an immediate that always (or never) holds
+  pub fn is_unconditional(&self) -> bool {
+    match self.opcode {
+      Opcode::JumpIfTrue => self.params[0].mode == ParameterMode::Immediate && self.params[0].value != 0,
+      Opcode::JumpIfFalse => self.params[0].mode == ParameterMode::Immediate && self.params[0].value == 0,
+      _ => false
+    }
+  }
+
+  // jumps that can never be taken, which makes them behave like a nop
+  pub fn is_never_taken(&self) -> bool {
+    match self.opcode {
+      Opcode::JumpIfTrue => self.params[0].mode == ParameterMode::Immediate && self.params[0].value == 0,
+      Opcode::JumpIfFalse => self.params[0].mode == ParameterMode::Immediate && self.params[0].value != 0,
+      _ => false
+    }
+  }
+
+  pub fn falls_through(&self) -> bool {
+    self.opcode != Opcode::Halt && !self.is_unconditional()
+  }
+}
+
+impl fmt::Display for Parameter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.mode {
+      ParameterMode::Position => write!(f, "[{}]", self.value),
+      ParameterMode::Immediate => write!(f, "#{}", self.value),
+      ParameterMode::Relative if self.value < 0 => write!(f, "rb{}", self.value),
+      ParameterMode::Relative => write!(f, "rb+{}", self.value)
+    }
+  }
+}
+
+impl fmt::Display for Instruction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let params: Vec<String> = self.params.iter().map(|param| param.to_string()).collect();
+    if params.is_empty() {
+      write!(f, "{}", self.opcode.mnemonic())
+    } else {
+      write!(f, "{:<4}{}", self.opcode.mnemonic(), params.join(", "))
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_decode() {
+    let instruction = Instruction::decode(&[21002, 4, 3, 4], 0).unwrap();
+
+    assert_eq!(instruction.opcode, Opcode::Mul);
+    assert_eq!(instruction.size(), 4);
+    assert_eq!(instruction.to_string(), "mul [4], #3, rb+4");
+  }
+
+  #[test]
+  fn test_decode_rejects_garbage() {
+    assert_eq!(Instruction::decode(&[42], 0), None);
+    assert_eq!(Instruction::decode(&[11101, 1, 1, 3], 0), None);
+    assert_eq!(Instruction::decode(&[1001, 1, 1], 0), None);
+    assert_eq!(Instruction::decode(&[30001, 1, 1, 3], 0), None);
+  }
+
+  #[test]
+  fn test_param_modes() {
+    assert_eq!(get_param_mode(2 * 10_i64.pow(18) + 1, MAX_PARAMS), Ok(ParameterMode::Relative));
+    assert_eq!(get_param_mode(i64::MAX, MAX_PARAMS + 1), Ok(ParameterMode::Position));
+    assert_eq!(get_param_mode(i64::MAX, usize::MAX), Ok(ParameterMode::Position));
+  }
+
+  #[test]
+  fn test_jumps() {
+    let jump = Instruction::decode(&[1105, 1, 9], 0).unwrap();
+    assert_eq!(jump.jump_target(), Some(9));
+    assert!(jump.is_unconditional());
+    assert!(!jump.falls_through());
+
+    let jump = Instruction::decode(&[2106, 0, -1], 0).unwrap();
+    assert_eq!(jump.jump_target(), None);
+    assert!(jump.is_unconditional());
+    assert_eq!(jump.to_string(), "jf  #0, rb-1");
+  }
+}
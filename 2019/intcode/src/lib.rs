@@ -0,0 +1,28 @@
+pub mod ascii;
+pub mod assemble;
+pub mod cfg;
+pub mod coverage;
+pub mod debugger;
+pub mod decompile;
+pub mod disassemble;
+mod error;
+#[cfg(test)]
+mod fuzz;
+pub mod hooks;
+pub mod inspect;
+pub mod instruction;
+pub mod loader;
+mod machine;
+mod memory;
+pub mod network;
+pub mod phases;
+pub mod search;
+pub mod snapshot;
+pub mod trace;
+pub mod transcript;
+pub mod transpile;
+
+pub use error::{ErrorKind, VmError};
+pub use instruction::{Instruction, Opcode, ParameterMode};
+pub use machine::{Machine, State};
+pub use memory::Memory;
@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum LoadError {
+  Io(io::Error),
+  // 1-based position of the offending text
+  Parse { line: usize, column: usize, message: String }
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      LoadError::Io(error) => write!(f, "{}", error),
+      LoadError::Parse { line, column, message } => write!(f, "line {}, column {}: {}", line, column, message)
+    }
+  }
+}
+
+impl Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+  fn from(error: io::Error) -> Self {
+    LoadError::Io(error)
+  }
+}
+
+fn parse_value(token: &str, line: usize, column: usize) -> Result<i64, LoadError> {
+  token.parse().map_err(|_| LoadError::Parse {
+    line,
+    column,
+    message: format!("invalid integer {:?}", token)
+  })
+}
+
+// values are separated by commas and any amount of whitespace, `#` starts a comment that runs to
+// the end of the line and a trailing comma is allowed
+pub fn parse(source: &str) -> Result<Vec<i64>, LoadError> {
+  let mut program = Vec::new();
+  // set after a comma until the next value shows up, so `1,,2` is caught
+  let mut expecting_value = false;
+
+  for (index, text) in source.lines().enumerate() {
+    let line = index + 1;
+    let code = text.split('#').next().unwrap();
+    let mut token = String::new();
+    let mut token_column = 0;
+
+    for (offset, c) in code.chars().chain(std::iter::once(' ')).enumerate() {
+      let column = offset + 1;
+      if c == ',' || c.is_whitespace() {
+        if !token.is_empty() {
+          program.push(parse_value(&token, line, token_column)?);
+          token.clear();
+          expecting_value = false;
+        }
+
+        if c == ',' {
+          if expecting_value || program.is_empty() {
+            return Err(LoadError::Parse { line, column, message: String::from("missing value before comma") });
+          }
+          expecting_value = true;
+        }
+      } else {
+        if token.is_empty() {
+          token_column = column;
+        }
+        token.push(c);
+      }
+    }
+  }
+
+  Ok(program)
+}
+
+pub fn load_reader<R: Read>(mut reader: R) -> Result<Vec<i64>, LoadError> {
+  let mut source = String::new();
+  reader.read_to_string(&mut source)?;
+
+  parse(&source)
+}
+
+// reads the program from a file, or from stdin when the path is `-`
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<i64>, LoadError> {
+  let path = path.as_ref();
+  if path == Path::new("-") {
+    return load_reader(io::stdin().lock());
+  }
+
+  parse(&fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn position(error: LoadError) -> (usize, usize) {
+    match error {
+      LoadError::Parse { line, column, .. } => (line, column),
+      LoadError::Io(error) => panic!("unexpected io error {}", error)
+    }
+  }
+
+  #[test]
+  fn test_parse() {
+    assert_eq!(parse("1,9,10,3,2,3,11,0,99,30,40,50\n").unwrap(), vec![1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+
+    let source = "
+      # reads a value and prints it back
+      3, 0,   # in [0]
+      4, 0,
+      99
+    ";
+    assert_eq!(parse(source).unwrap(), vec![3, 0, 4, 0, 99]);
+    assert_eq!(parse("1 2\t-3,").unwrap(), vec![1, 2, -3]);
+    assert_eq!(parse("").unwrap(), Vec::<i64>::new());
+  }
+
+  #[test]
+  fn test_errors() {
+    assert_eq!(position(parse("1,2,x3,4").unwrap_err()), (1, 5));
+    assert_eq!(position(parse("1,2\n  3,4o").unwrap_err()), (2, 5));
+    assert_eq!(position(parse("1,\n,2").unwrap_err()), (2, 1));
+    assert_eq!(position(parse(",1").unwrap_err()), (1, 1));
+
+    let error = parse("99999999999999999999").unwrap_err();
+    assert_eq!(error.to_string(), "line 1, column 1: invalid integer \"99999999999999999999\"");
+  }
+
+  #[test]
+  fn test_load() {
+    let path = std::env::temp_dir().join(format!("intcode-loader-{}.txt", std::process::id()));
+    fs::write(&path, "104,7,99 # prints seven\n").unwrap();
+    let program = load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(program, vec![104, 7, 99]);
+    assert_eq!(load_reader("1,2".as_bytes()).unwrap(), vec![1, 2]);
+    assert!(matches!(load(std::env::temp_dir().join("intcode-loader-missing")), Err(LoadError::Io(_))));
+  }
+}
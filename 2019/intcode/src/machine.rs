@@ -0,0 +1,774 @@
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use crate::error::{ErrorKind, VmError};
+use crate::hooks::{CustomOpcode, Device, HookError, Hooks, OpcodeContext};
+use crate::instruction::{self, Opcode, ParameterMode};
+use crate::memory::Memory;
+use crate::trace::{TraceEvent, Tracer};
+use crate::transcript::{Entry, Transcript};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum State {
+  NeedsInput,
+  Output(i64),
+  Halted,
+  // ran out of instructions or time, holds the pc of the next instruction
+  BudgetExceeded(usize)
+}
+
+// how many instructions run between two looks at the clock
+const DEADLINE_INTERVAL: u64 = 1024;
+
+// an instruction word split into its opcode and parameter modes
+#[derive(Debug, Clone, Copy)]
+struct Decoded {
+  opcode: Opcode,
+  // bad mode digits are kept and only reported once the parameter is actually used
+  modes: [Result<ParameterMode, i64>; 3]
+}
+
+impl Decoded {
+  // None when the opcode is not a built-in one
+  fn new(word: i64) -> Option<Decoded> {
+    let opcode = Opcode::from_code(instruction::get_opcode(word))?;
+    let mut modes = [Ok(ParameterMode::Position); 3];
+    for (index, mode) in modes.iter_mut().enumerate().take(opcode.param_count()) {
+      *mode = instruction::get_param_mode(word, index + 1);
+    }
+
+    Some(Decoded { opcode, modes })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct Machine {
+  pub pc: usize,
+  pub memory: Memory,
+  pub input: VecDeque<i64>,
+  pub relative_offset: i64,
+  pub(crate) halted: bool,
+  // address and value of the most recent write, devices included
+  last_write: Option<(usize, i64)>,
+  executed: u64,
+  instruction_limit: Option<u64>,
+  deadline: Option<Instant>,
+  recording: Option<Vec<Entry>>,
+  hooks: Hooks
+}
+
+impl Machine {
+  pub fn new(memory: Vec<i64>, input: Vec<i64>) -> Self {
+    Self {
+      pc: 0,
+      memory: Memory::new(memory),
+      input: input.into(),
+      relative_offset: 0,
+      halted: false,
+      last_write: None,
+      executed: 0,
+      instruction_limit: None,
+      deadline: None,
+      recording: None,
+      hooks: Hooks::default()
+    }
+  }
+
+  pub(crate) fn error(&self, operand: Option<usize>, kind: ErrorKind) -> VmError {
+    VmError {
+      pc: self.pc,
+      instruction: self.memory.read(self.pc).unwrap_or(0),
+      operand,
+      kind
+    }
+  }
+
+  fn get_instruction(&self) -> Result<i64, VmError> {
+    match self.memory.read(self.pc) {
+      Some(instruction) => Ok(instruction),
+      None => Err(self.error(None, ErrorKind::AddressOutOfRange(self.pc)))
+    }
+  }
+
+  fn get_opcode(&self) -> Result<i64, VmError> {
+    Ok(instruction::get_opcode(self.get_instruction()?))
+  }
+
+  fn get_param_mode(&self, offset: usize) -> Result<ParameterMode, VmError> {
+    instruction::get_param_mode(self.get_instruction()?, offset)
+      .map_err(|mode| self.error(Some(offset), ErrorKind::InvalidParameterMode(mode)))
+  }
+
+  fn read(&self, address: usize, operand: usize) -> Result<i64, VmError> {
+    if let Some(device) = self.hooks.device(address) {
+      return Ok(device.lock().unwrap().read(address));
+    }
+
+    match self.memory.read(address) {
+      Some(value) => Ok(value),
+      None => Err(self.error(Some(operand), ErrorKind::AddressOutOfRange(address)))
+    }
+  }
+
+  pub(crate) fn write(&mut self, offset: usize, value: i64) -> Result<(), VmError> {
+    if self.get_param_mode(offset)? == ParameterMode::Immediate {
+      return Err(self.error(Some(offset), ErrorKind::ImmediateWrite));
+    }
+
+    let address = self.get_address(offset)?;
+    self.store(address, offset, value)
+  }
+
+  fn store(&mut self, address: usize, operand: usize, value: i64) -> Result<(), VmError> {
+    if let Some(device) = self.hooks.device(address) {
+      device.lock().unwrap().write(address, value);
+    } else if self.memory.write(address, value).is_none() {
+      return Err(self.error(Some(operand), ErrorKind::AddressOutOfRange(address)));
+    }
+
+    self.last_write = Some((address, value));
+    Ok(())
+  }
+
+  pub(crate) fn get_param_value(&self, offset: usize) -> Result<i64, VmError> {
+    let address = self.get_address(offset)?;
+
+    self.read(address, offset)
+  }
+
+  fn get_address(&self, offset: usize) -> Result<usize, VmError> {
+    self.resolve(self.get_param_mode(offset)?, offset)
+  }
+
+  // address a parameter refers to, which for immediates is the parameter cell itself
+  fn resolve(&self, param_mode: ParameterMode, offset: usize) -> Result<usize, VmError> {
+    let address = self.pc + offset;
+
+    let address = match param_mode {
+      ParameterMode::Position => self.read(address, offset)?,
+      ParameterMode::Relative => self.read(address, offset)?
+        .checked_add(self.relative_offset)
+        .ok_or_else(|| self.error(Some(offset), ErrorKind::Overflow))?,
+      ParameterMode::Immediate => return Ok(address)
+    };
+
+    if address < 0 {
+      Err(self.error(Some(offset), ErrorKind::NegativeAddress(address)))
+    } else {
+      Ok(address as usize)
+    }
+  }
+
+  pub(crate) fn jump(&mut self, target: i64) -> Result<usize, VmError> {
+    if target < 0 {
+      return Err(self.error(Some(2), ErrorKind::NegativeAddress(target)));
+    }
+
+    self.pc = target as usize;
+    Ok(0)
+  }
+
+  // caps addressable memory so runaway programs fail instead of allocating forever
+  pub fn set_memory_limit(&mut self, limit: Option<usize>) {
+    self.memory.set_limit(limit);
+  }
+
+  // allows this many more instructions before execution stops with BudgetExceeded
+  pub fn set_budget(&mut self, budget: Option<u64>) {
+    self.instruction_limit = budget.map(|budget| self.executed.saturating_add(budget));
+  }
+
+  // wall clock time after which execution stops with BudgetExceeded, checked every few
+  // instructions so it costs next to nothing
+  pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+    self.deadline = deadline;
+  }
+
+  // runs the handler for an opcode the interpreter does not know, the machine moves past the
+  // parameters afterwards unless the handler jumped, asked for input or halted
+  pub fn register_opcode<F>(&mut self, code: i64, params: usize, handler: F) -> Result<(), HookError>
+  where
+    F: Fn(&mut OpcodeContext) -> Result<Option<State>, VmError> + Send + Sync + 'static
+  {
+    if !(0..100).contains(&code) || Opcode::from_code(code).is_some() {
+      return Err(HookError::OpcodeTaken(code));
+    }
+    if params > instruction::MAX_PARAMS {
+      return Err(HookError::TooManyParams(params));
+    }
+
+    self.hooks.opcodes.insert(code, CustomOpcode { params, handler: Arc::new(handler) });
+    Ok(())
+  }
+
+  // sends every data read and write in the range to the device instead of memory, the returned
+  // handle gives access to the device later on
+  pub fn map_device<D>(&mut self, range: Range<usize>, device: D) -> Result<Arc<Mutex<D>>, HookError>
+  where
+    D: Device + 'static
+  {
+    let overlaps = self.hooks.devices
+      .iter()
+      .any(|(mapped, _)| range.start < mapped.end && mapped.start < range.end);
+    if overlaps {
+      return Err(HookError::Overlap(range));
+    }
+
+    let device = Arc::new(Mutex::new(device));
+    self.hooks.devices.push((range, device.clone()));
+
+    Ok(device)
+  }
+
+  // starts a fresh transcript of every input consumed and output produced
+  pub fn start_recording(&mut self) {
+    self.recording = Some(Vec::new());
+  }
+
+  // stops recording and hands over what was recorded so far
+  pub fn take_transcript(&mut self) -> Option<Transcript> {
+    self.recording.take().map(|entries| Transcript { entries })
+  }
+
+  pub(crate) fn recorded(&self) -> &[Entry] {
+    self.recording.as_deref().unwrap_or(&[])
+  }
+
+  pub fn instructions_executed(&self) -> u64 {
+    self.executed
+  }
+
+  pub fn add_input(&mut self, input_value: i64) {
+    self.input.push_back(input_value);
+  }
+
+  pub fn is_halted(&self) -> bool {
+    self.halted
+  }
+
+  // address written by the most recent step, if it wrote anything
+  pub fn last_write(&self) -> Option<usize> {
+    self.last_write.map(|(address, _)| address)
+  }
+
+  // runs until the machine halts, blocks on input or exceeds its budget, collecting every output
+  // along the way
+  pub fn run(&mut self) -> Result<Vec<i64>, VmError> {
+    let mut output = Vec::new();
+    while let State::Output(value) = self.execute()? {
+      output.push(value);
+    }
+
+    Ok(output)
+  }
+
+  // runs until the next output, until input is needed but not queued, until the budget is spent
+  // or until halted
+  pub fn execute(&mut self) -> Result<State, VmError> {
+    loop {
+      self.run_fast();
+      if let Some(state) = self.step()? {
+        return Ok(state);
+      }
+    }
+  }
+
+  pub fn run_traced(&mut self, tracer: &mut dyn Tracer) -> Result<Vec<i64>, VmError> {
+    let mut output = Vec::new();
+    while let State::Output(value) = self.execute_traced(tracer)? {
+      output.push(value);
+    }
+
+    Ok(output)
+  }
+
+  // same as execute, but hands every executed instruction to the tracer
+  pub fn execute_traced(&mut self, tracer: &mut dyn Tracer) -> Result<State, VmError> {
+    loop {
+      if let Some(state) = self.step_traced(tracer)? {
+        return Ok(state);
+      }
+    }
+  }
+
+  pub fn step_traced(&mut self, tracer: &mut dyn Tracer) -> Result<Option<State>, VmError> {
+    let pc = self.pc;
+    let instruction = self.memory.read(pc).unwrap_or(0);
+    let (operands, reads) = self.read_operands();
+
+    let state = self.step()?;
+    if let None | Some(State::Output(_)) | Some(State::Halted) = state {
+      tracer.trace(&TraceEvent { pc, instruction, operands, reads, write: self.last_write });
+    }
+
+    Ok(state)
+  }
+
+  // values of every parameter the current instruction reads, resolved before it executes, along
+  // with the data addresses they came from
+  fn read_operands(&self) -> (Vec<i64>, Vec<usize>) {
+    let opcode = match self.get_opcode().ok().and_then(Opcode::from_code) {
+      Some(opcode) => opcode,
+      None => return (Vec::new(), Vec::new())
+    };
+
+    let (mut operands, mut reads) = (Vec::new(), Vec::new());
+    for offset in (1..=opcode.param_count()).filter(|offset| opcode.write_param() != Some(*offset)) {
+      let mode = self.get_param_mode(offset).ok();
+      let address = mode.and_then(|mode| self.peek_address(mode, offset));
+      operands.push(address.and_then(|address| self.memory.read(address)).unwrap_or(0));
+      if let (Some(address), Some(ParameterMode::Position | ParameterMode::Relative)) = (address, mode) {
+        reads.push(address);
+      }
+    }
+
+    (operands, reads)
+  }
+
+  // same as resolve, but straight from memory so tracing never triggers a device read
+  fn peek_address(&self, param_mode: ParameterMode, offset: usize) -> Option<usize> {
+    let cell = self.pc + offset;
+    let address = match param_mode {
+      ParameterMode::Position => self.memory.read(cell)?,
+      ParameterMode::Relative => self.memory.read(cell)?.checked_add(self.relative_offset)?,
+      ParameterMode::Immediate => return Some(cell)
+    };
+
+    usize::try_from(address).ok()
+  }
+
+  // executes a single instruction, returning a state only when the machine has something to report
+  pub fn step(&mut self) -> Result<Option<State>, VmError> {
+    if self.halted {
+      return Err(self.error(None, ErrorKind::Halted));
+    }
+
+    self.last_write = None;
+    if self.instruction_limit.map(|limit| self.executed >= limit).unwrap_or(false) {
+      return Ok(Some(State::BudgetExceeded(self.pc)));
+    }
+    if let Some(deadline) = self.deadline {
+      if self.executed.is_multiple_of(DEADLINE_INTERVAL) && Instant::now() >= deadline {
+        return Ok(Some(State::BudgetExceeded(self.pc)));
+      }
+    }
+
+    let (pc, queued, next_input) = (self.pc, self.input.len(), self.input.front().copied());
+    let state = match Decoded::new(self.get_instruction()?) {
+      Some(decoded) => self.dispatch(&decoded)?,
+      None => self.dispatch_custom(self.get_opcode()?)?
+    };
+    if state != Some(State::NeedsInput) {
+      self.executed += 1;
+    }
+
+    if let Some(recording) = self.recording.as_mut() {
+      if let (true, Some(value)) = (self.input.len() < queued, next_input) {
+        recording.push(Entry::Input { pc, value });
+      }
+      if let Some(State::Output(value)) = state {
+        recording.push(Entry::Output { pc, value });
+      }
+    }
+
+    Ok(state)
+  }
+
+  // runs plain instructions straight on the contiguous memory for as long as none of them needs
+  // a full step. Input, output, halting, errors, devices, the budget, the deadline and addresses
+  // past the contiguous memory all stop it before the instruction has any effect
+  fn run_fast(&mut self) {
+    if self.halted || !self.hooks.devices.is_empty() {
+      return;
+    }
+
+    let mut stop = self.instruction_limit.unwrap_or(u64::MAX);
+    if self.deadline.is_some() {
+      stop = stop.min(self.executed.div_ceil(DEADLINE_INTERVAL) * DEADLINE_INTERVAL);
+    }
+
+    let (mut pc, mut relative_offset, mut executed) = (self.pc, self.relative_offset, self.executed);
+    let memory = self.memory.contiguous_mut();
+    while executed < stop {
+      let word = match memory.get(pc) {
+        Some(word) if *word > 0 => *word,
+        _ => break
+      };
+      let modes = [word / 100 % 10, word / 1000 % 10, word / 10000 % 10];
+      // the cell a parameter refers to
+      let address = |offset: usize| -> Option<usize> {
+        let cell = pc + offset;
+        let address = match modes[offset - 1] {
+          0 => *memory.get(cell)?,
+          1 => return Some(cell).filter(|cell| *cell < memory.len()),
+          2 => memory.get(cell)?.checked_add(relative_offset)?,
+          _ => return None
+        };
+
+        usize::try_from(address).ok().filter(|address| *address < memory.len())
+      };
+
+      let opcode = word % 100;
+      match opcode {
+        1 | 2 | 7 | 8 => {
+          let operands = (address(1), address(2), address(3));
+          let (a, b, target) = match operands {
+            (Some(a), Some(b), Some(target)) if modes[2] != 1 => (memory[a], memory[b], target),
+            _ => break
+          };
+          let value = match opcode {
+            1 => a.checked_add(b),
+            2 => a.checked_mul(b),
+            7 => Some((a < b) as i64),
+            _ => Some((a == b) as i64)
+          };
+          match value {
+            Some(value) => memory[target] = value,
+            None => break
+          }
+          pc += 4;
+        },
+        5 | 6 => {
+          let condition = match address(1) {
+            Some(cell) => memory[cell],
+            None => break
+          };
+          if (condition != 0) == (opcode == 5) {
+            match address(2).and_then(|cell| usize::try_from(memory[cell]).ok()) {
+              Some(target) => pc = target,
+              None => break
+            }
+          } else {
+            pc += 3;
+          }
+        },
+        9 => {
+          match address(1).and_then(|cell| relative_offset.checked_add(memory[cell])) {
+            Some(offset) => relative_offset = offset,
+            None => break
+          }
+          pc += 2;
+        },
+        _ => break
+      }
+      executed += 1;
+    }
+
+    self.pc = pc;
+    self.relative_offset = relative_offset;
+    self.executed = executed;
+  }
+
+  fn mode(&self, decoded: &Decoded, offset: usize) -> Result<ParameterMode, VmError> {
+    decoded.modes[offset - 1].map_err(|mode| self.error(Some(offset), ErrorKind::InvalidParameterMode(mode)))
+  }
+
+  fn operand(&self, decoded: &Decoded, offset: usize) -> Result<i64, VmError> {
+    let address = self.resolve(self.mode(decoded, offset)?, offset)?;
+
+    self.read(address, offset)
+  }
+
+  fn store_operand(&mut self, decoded: &Decoded, offset: usize, value: i64) -> Result<(), VmError> {
+    let mode = self.mode(decoded, offset)?;
+    if mode == ParameterMode::Immediate {
+      return Err(self.error(Some(offset), ErrorKind::ImmediateWrite));
+    }
+
+    let address = self.resolve(mode, offset)?;
+    self.store(address, offset, value)
+  }
+
+  fn dispatch(&mut self, decoded: &Decoded) -> Result<Option<State>, VmError> {
+    let step = match decoded.opcode {
+      Opcode::Add => {
+        let (p1, p2) = (self.operand(decoded, 1)?, self.operand(decoded, 2)?);
+        let value = p1.checked_add(p2).ok_or_else(|| self.error(None, ErrorKind::Overflow))?;
+        self.store_operand(decoded, 3, value)?;
+
+        4
+      },
+      Opcode::Mul => {
+        let (p1, p2) = (self.operand(decoded, 1)?, self.operand(decoded, 2)?);
+        let value = p1.checked_mul(p2).ok_or_else(|| self.error(None, ErrorKind::Overflow))?;
+        self.store_operand(decoded, 3, value)?;
+
+        4
+      },
+      Opcode::Input => {
+        match self.input.front() {
+          Some(value) => self.store_operand(decoded, 1, *value)?,
+          None => return Ok(Some(State::NeedsInput))
+        }
+        self.input.pop_front();
+
+        2
+      },
+      Opcode::Output => {
+        let value = self.operand(decoded, 1)?;
+        self.pc += 2;
+
+        return Ok(Some(State::Output(value)));
+      },
+      Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+        let value = self.operand(decoded, 1)?;
+        if (value != 0) == (decoded.opcode == Opcode::JumpIfTrue) {
+          let target = self.operand(decoded, 2)?;
+          self.jump(target)?
+        } else {
+          3
+        }
+      },
+      Opcode::LessThan => {
+        let (p1, p2) = (self.operand(decoded, 1)?, self.operand(decoded, 2)?);
+        self.store_operand(decoded, 3, (p1 < p2) as i64)?;
+
+        4
+      },
+      Opcode::Equals => {
+        let (p1, p2) = (self.operand(decoded, 1)?, self.operand(decoded, 2)?);
+        self.store_operand(decoded, 3, (p1 == p2) as i64)?;
+
+        4
+      },
+      Opcode::AdjustBase => {
+        let shift = self.operand(decoded, 1)?;
+        self.relative_offset = self.relative_offset
+          .checked_add(shift)
+          .ok_or_else(|| self.error(None, ErrorKind::Overflow))?;
+
+        2
+      },
+      Opcode::Halt => {
+        self.halted = true;
+        return Ok(Some(State::Halted));
+      }
+    };
+
+    self.pc += step;
+    Ok(None)
+  }
+
+  fn dispatch_custom(&mut self, code: i64) -> Result<Option<State>, VmError> {
+    let custom = match self.hooks.opcodes.get(&code) {
+      Some(custom) => custom.clone(),
+      None => return Err(self.error(None, ErrorKind::InvalidOpcode))
+    };
+
+    let mut context = OpcodeContext { machine: self, params: custom.params, jumped: false };
+    let state = (custom.handler)(&mut context)?;
+    let jumped = context.jumped;
+
+    match state {
+      Some(State::NeedsInput) => {},
+      Some(State::Halted) => self.halted = true,
+      _ if !jumped => self.pc += custom.params + 1,
+      _ => {}
+    }
+
+    Ok(state)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  fn parse(program: &str) -> Vec<i64> {
+    program
+      .split(',')
+      .map(|el| el.parse().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn test_param_modes() {
+    let machine = Machine::new(parse("21002,4,3,4,33"), Vec::new());
+
+    assert_eq!(machine.get_opcode(), Ok(2));
+    assert_eq!(machine.get_param_mode(1), Ok(ParameterMode::Position));
+    assert_eq!(machine.get_param_mode(2), Ok(ParameterMode::Immediate));
+    assert_eq!(machine.get_param_mode(3), Ok(ParameterMode::Relative));
+  }
+
+  #[test]
+  fn test_compare() {
+    let program = parse("3,9,8,9,10,9,4,9,99,-1,8");
+
+    let mut machine = Machine::new(program.clone(), vec![8]);
+    assert_eq!(machine.run().unwrap(), vec![1]);
+
+    let mut machine = Machine::new(program, vec![7]);
+    assert_eq!(machine.run().unwrap(), vec![0]);
+  }
+
+  #[test]
+  fn test_jumps() {
+    let program = parse("3,3,1105,-1,9,1101,0,0,12,4,12,99,1");
+
+    let mut machine = Machine::new(program.clone(), vec![0]);
+    assert_eq!(machine.run().unwrap(), vec![0]);
+
+    let mut machine = Machine::new(program, vec![5]);
+    assert_eq!(machine.run().unwrap(), vec![1]);
+  }
+
+  #[test]
+  fn test_states() {
+    let mut machine = Machine::new(parse("104,1,3,9,4,9,99,0,0,0"), Vec::new());
+
+    assert_eq!(machine.execute().unwrap(), State::Output(1));
+    assert_eq!(machine.execute().unwrap(), State::NeedsInput);
+    assert_eq!(machine.execute().unwrap(), State::NeedsInput);
+    assert!(!machine.is_halted());
+
+    machine.add_input(42);
+    assert_eq!(machine.execute().unwrap(), State::Output(42));
+    assert_eq!(machine.execute().unwrap(), State::Halted);
+    assert!(machine.is_halted());
+  }
+
+  #[test]
+  fn test_errors() {
+    let mut machine = Machine::new(parse("1,0,0,0,42"), Vec::new());
+    machine.step().unwrap();
+    assert_eq!(machine.execute(), Err(VmError {
+      pc: 4,
+      instruction: 42,
+      operand: None,
+      kind: ErrorKind::InvalidOpcode
+    }));
+
+    let mut machine = Machine::new(parse("109,-5,204,1,99"), Vec::new());
+    let error = machine.execute().unwrap_err();
+    assert_eq!((error.pc, error.operand), (2, Some(1)));
+    assert_eq!(error.kind, ErrorKind::NegativeAddress(-4));
+
+    let mut machine = Machine::new(parse("1101,1,1,20000,99"), Vec::new());
+    machine.set_memory_limit(Some(10000));
+    assert_eq!(machine.execute().unwrap_err().kind, ErrorKind::AddressOutOfRange(20000));
+
+    let mut machine = Machine::new(parse("11101,1,1,3,99"), Vec::new());
+    assert_eq!(machine.execute().unwrap_err().kind, ErrorKind::ImmediateWrite);
+
+    let mut machine = Machine::new(vec![1101, i64::MAX, 1, 0, 99], Vec::new());
+    assert_eq!(machine.execute().unwrap_err().kind, ErrorKind::Overflow);
+    let mut machine = Machine::new(vec![1102, i64::MIN, -1, 0, 99], Vec::new());
+    assert_eq!(machine.execute().unwrap_err().kind, ErrorKind::Overflow);
+
+    let mut machine = Machine::new(vec![109, i64::MAX, 109, 1, 99], Vec::new());
+    let error = machine.execute().unwrap_err();
+    assert_eq!((error.pc, error.kind), (2, ErrorKind::Overflow));
+
+    let mut machine = Machine::new(vec![109, i64::MAX, 204, 1, 99], Vec::new());
+    let error = machine.execute().unwrap_err();
+    assert_eq!((error.pc, error.operand, error.kind), (2, Some(1), ErrorKind::Overflow));
+
+    let mut machine = Machine::new(parse("99"), Vec::new());
+    assert_eq!(machine.execute(), Ok(State::Halted));
+    assert_eq!(machine.execute().unwrap_err().kind, ErrorKind::Halted);
+  }
+
+  #[test]
+  fn test_memory_grows() {
+    let mut machine = Machine::new(parse("1101,2,3,123456,4,123456,99"), Vec::new());
+    assert_eq!(machine.run().unwrap(), vec![5]);
+    assert_eq!(machine.memory.read(123456), Some(5));
+
+    let mut machine = Machine::new(parse("104,0,99"), Vec::new());
+    machine.run().unwrap();
+    assert_eq!(machine.memory.allocated(), 3);
+  }
+
+  #[test]
+  fn test_budget() {
+    // loops forever
+    let mut machine = Machine::new(parse("1105,1,0"), Vec::new());
+    machine.set_budget(Some(100));
+
+    assert_eq!(machine.execute(), Ok(State::BudgetExceeded(0)));
+    assert_eq!(machine.instructions_executed(), 100);
+    assert_eq!(machine.execute(), Ok(State::BudgetExceeded(0)));
+
+    machine.set_budget(Some(1));
+    assert_eq!(machine.execute(), Ok(State::BudgetExceeded(0)));
+    assert_eq!(machine.instructions_executed(), 101);
+
+    let mut machine = Machine::new(parse("3,0,4,0,99"), Vec::new());
+    machine.set_budget(Some(2));
+    assert_eq!(machine.execute(), Ok(State::NeedsInput));
+    machine.add_input(5);
+    assert_eq!(machine.execute(), Ok(State::Output(5)));
+    assert_eq!(machine.execute(), Ok(State::BudgetExceeded(4)));
+  }
+
+  #[test]
+  fn test_deadline() {
+    let mut machine = Machine::new(parse("1105,1,0"), Vec::new());
+    machine.set_deadline(Some(Instant::now()));
+
+    assert_eq!(machine.execute(), Ok(State::BudgetExceeded(0)));
+  }
+
+  #[test]
+  fn test_self_modifying() {
+    // runs the instruction at op as an add, then rewrites it into a mul and runs it again
+    let program = crate::assemble::assemble("
+      op:     add [value], #2, [value]
+              out [value]
+              jt  [done], #end
+              add #1002, #0, [op]
+              add #1, #0, [done]
+              jt  #1, #op
+      end:    hlt
+      value:  .data 3
+      done:   .data 0
+    ").unwrap();
+
+    let mut machine = Machine::new(program, Vec::new());
+    assert_eq!(machine.run().unwrap(), vec![5, 10]);
+  }
+
+  #[test]
+  fn test_fast_path() {
+    let programs = [
+      ("3,9,8,9,10,9,4,9,99,-1,8", vec![8]),
+      ("3,3,1105,-1,9,1101,0,0,12,4,12,99,1", vec![5]),
+      ("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99", vec![]),
+      ("109,-5,204,1,99", vec![]),
+      ("11101,1,1,3,99", vec![]),
+      ("1,0,0,0,42", vec![]),
+      ("1101,1,1,30,1105,1,0", vec![])
+    ];
+
+    for (program, input) in programs.iter() {
+      let mut fast = Machine::new(parse(program), input.clone());
+      let mut stepped = Machine::new(parse(program), input.clone());
+      fast.set_budget(Some(1000));
+      stepped.set_budget(Some(1000));
+
+      let mut expected = Vec::new();
+      let state = loop {
+        match stepped.step() {
+          Ok(None) => {},
+          Ok(Some(State::Output(value))) => expected.push(value),
+          other => break other.map(|state| state.unwrap())
+        }
+      };
+      let mut outputs = Vec::new();
+      let fast_state = loop {
+        match fast.execute() {
+          Ok(State::Output(value)) => outputs.push(value),
+          other => break other
+        }
+      };
+
+      assert_eq!((fast_state, outputs), (state, expected), "{}", program);
+      assert_eq!(fast.pc, stepped.pc);
+      assert_eq!(fast.memory, stepped.memory);
+      assert_eq!(fast.instructions_executed(), stepped.instructions_executed());
+    }
+  }
+}
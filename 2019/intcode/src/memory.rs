@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+// addresses below this live in one contiguous vector, anything higher is paged
+const DENSE_LIMIT: usize = 1 << 20;
+const PAGE_SIZE: usize = 1024;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Memory {
+  dense: Vec<i64>,
+  pages: HashMap<usize, Vec<i64>>,
+  limit: Option<usize>
+}
+
+impl Memory {
+  pub fn new(program: Vec<i64>) -> Self {
+    Self {
+      dense: program,
+      pages: HashMap::new(),
+      limit: None
+    }
+  }
+
+  pub fn limit(&self) -> Option<usize> {
+    self.limit
+  }
+
+  // addresses at or past the limit are rejected by both reads and writes
+  pub fn set_limit(&mut self, limit: Option<usize>) {
+    self.limit = limit;
+  }
+
+  fn in_bounds(&self, address: usize) -> bool {
+    match self.limit {
+      Some(limit) => address < limit,
+      None => true
+    }
+  }
+
+  // unwritten cells read as zero, None means the address is past the limit
+  pub fn read(&self, address: usize) -> Option<i64> {
+    if !self.in_bounds(address) {
+      return None;
+    }
+
+    if address < DENSE_LIMIT {
+      Some(self.dense.get(address).copied().unwrap_or(0))
+    } else {
+      let value = self.pages
+        .get(&(address / PAGE_SIZE))
+        .map(|page| page[address % PAGE_SIZE])
+        .unwrap_or(0);
+
+      Some(value)
+    }
+  }
+
+  pub fn write(&mut self, address: usize, value: i64) -> Option<()> {
+    if !self.in_bounds(address) {
+      return None;
+    }
+
+    if address < DENSE_LIMIT {
+      if address >= self.dense.len() {
+        self.dense.resize(address + 1, 0);
+      }
+      self.dense[address] = value;
+    } else {
+      let page = self.pages
+        .entry(address / PAGE_SIZE)
+        .or_insert_with(|| vec![0; PAGE_SIZE]);
+      page[address % PAGE_SIZE] = value;
+    }
+
+    Some(())
+  }
+
+  // the contiguous low memory, which holds the program and anything it grew into
+  pub fn as_slice(&self) -> &[i64] {
+    &self.dense
+  }
+
+  // the contiguous low memory below the limit, for hot loops that skip the per access checks
+  pub(crate) fn contiguous_mut(&mut self) -> &mut [i64] {
+    let len = self.limit.map(|limit| limit.min(self.dense.len())).unwrap_or(self.dense.len());
+    &mut self.dense[..len]
+  }
+
+  // non-zero cells that live in the paged high memory, sorted by address
+  pub fn paged_cells(&self) -> Vec<(usize, i64)> {
+    let mut cells: Vec<(usize, i64)> = self.pages
+      .iter()
+      .flat_map(|(page, values)| {
+        values
+          .iter()
+          .enumerate()
+          .filter(|(_, value)| **value != 0)
+          .map(move |(offset, value)| (page * PAGE_SIZE + offset, *value))
+      })
+      .collect();
+    cells.sort_unstable();
+
+    cells
+  }
+
+  // number of cells actually backed by storage
+  pub fn allocated(&self) -> usize {
+    self.dense.len() + self.pages.len() * PAGE_SIZE
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_grows_on_write() {
+    let mut memory = Memory::new(vec![1, 2, 3]);
+
+    assert_eq!(memory.read(100), Some(0));
+    assert_eq!(memory.allocated(), 3);
+
+    memory.write(100, 7).unwrap();
+    assert_eq!(memory.read(100), Some(7));
+    assert_eq!(memory.read(2), Some(3));
+    assert_eq!(memory.allocated(), 101);
+  }
+
+  #[test]
+  fn test_high_addresses_are_paged() {
+    let mut memory = Memory::new(Vec::new());
+    let address = 1_000_000_000_000;
+
+    memory.write(address, 5).unwrap();
+    assert_eq!(memory.read(address), Some(5));
+    assert_eq!(memory.read(address + 1), Some(0));
+    assert_eq!(memory.allocated(), PAGE_SIZE);
+  }
+
+  #[test]
+  fn test_limit() {
+    let mut memory = Memory::new(vec![0; 10]);
+    memory.set_limit(Some(16));
+
+    assert_eq!(memory.write(15, 1), Some(()));
+    assert_eq!(memory.write(16, 1), None);
+    assert_eq!(memory.read(16), None);
+  }
+}
@@ -0,0 +1,482 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::error::VmError;
+use crate::machine::{Machine, State};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkErrorKind {
+  Vm(VmError),
+  // the machine wants input but every node that could send it has stopped
+  InputClosed,
+  // every node still running waits for input and nothing is on its way
+  Deadlock,
+  // the topology names a node the network does not have, as its entry, exit or an edge end
+  NoSuchNode,
+  // the network got this many machines instead of one per node
+  MachineCount(usize),
+  // the machine ran out of instructions or time at the given pc
+  BudgetExceeded(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkError {
+  pub node: usize,
+  pub kind: NetworkErrorKind
+}
+
+impl fmt::Display for NetworkError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match &self.kind {
+      NetworkErrorKind::Vm(error) => write!(f, "node {}: {}", self.node, error),
+      NetworkErrorKind::InputClosed => write!(f, "node {}: waiting on input that will never arrive", self.node),
+      NetworkErrorKind::Deadlock => write!(f, "node {}: deadlocked, every node is waiting on input", self.node),
+      NetworkErrorKind::NoSuchNode => write!(f, "node {}: not in the network", self.node),
+      NetworkErrorKind::MachineCount(count) => write!(f, "node {}: got {} machines, need one per node", self.node, count),
+      NetworkErrorKind::BudgetExceeded(pc) => write!(f, "node {}: budget exceeded @ {}", self.node, pc)
+    }
+  }
+}
+
+impl Error for NetworkError {}
+
+// directed graph of machines, every output of a node is sent to all of its successors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Topology {
+  pub nodes: usize,
+  pub edges: Vec<(usize, usize)>,
+  // node that receives the network input
+  pub entry: usize,
+  // node whose outputs are the result of the network
+  pub exit: usize
+}
+
+impl Topology {
+  pub fn new(nodes: usize) -> Self {
+    Self {
+      nodes,
+      edges: Vec::new(),
+      entry: 0,
+      exit: nodes.saturating_sub(1)
+    }
+  }
+
+  pub fn chain(nodes: usize) -> Self {
+    let mut topology = Topology::new(nodes);
+    for node in 1..nodes {
+      topology.connect(node - 1, node);
+    }
+
+    topology
+  }
+
+  pub fn ring(nodes: usize) -> Self {
+    let mut topology = Topology::chain(nodes);
+    if nodes > 0 {
+      topology.connect(nodes - 1, 0);
+    }
+
+    topology
+  }
+
+  // edges are checked when the network is built, like the ones pushed to `edges` directly
+  pub fn connect(&mut self, from: usize, to: usize) -> &mut Self {
+    self.edges.push((from, to));
+    self
+  }
+
+  // fails on the first entry, exit or edge end that is not a node of the network
+  pub fn validate(&self) -> Result<(), NetworkError> {
+    let ends = self.edges.iter().flat_map(|(from, to)| vec![*from, *to]);
+    match [self.entry, self.exit].iter().copied().chain(ends).find(|node| *node >= self.nodes.max(1)) {
+      Some(node) => Err(NetworkError { node, kind: NetworkErrorKind::NoSuchNode }),
+      None => Ok(())
+    }
+  }
+
+  pub fn successors(&self, node: usize) -> Vec<usize> {
+    self.edges.iter().filter(|(from, _)| *from == node).map(|(_, to)| *to).collect()
+  }
+}
+
+// how often a node waiting on input checks whether the whole network is stuck
+const DEADLOCK_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct Network {
+  machines: Vec<Machine>,
+  topology: Topology
+}
+
+// shared by all nodes so they can tell a slow network from a stuck one. In a cycle every node
+// keeps a sender to its successor alive, so a closed channel never reports that case
+#[derive(Debug, Default)]
+struct Traffic {
+  // nodes that have not stopped yet
+  running: usize,
+  // running nodes waiting on input
+  waiting: usize,
+  // values sent but not received yet
+  in_flight: usize
+}
+
+// blocks until a value arrives, failing once nobody is left to send one
+fn receive(node: usize, input: &Receiver<i64>, traffic: &Mutex<Traffic>) -> Result<i64, NetworkError> {
+  traffic.lock().unwrap().waiting += 1;
+  loop {
+    let received = input.recv_timeout(DEADLOCK_INTERVAL);
+
+    let mut traffic = traffic.lock().unwrap();
+    let kind = match received {
+      Ok(value) => {
+        traffic.in_flight -= 1;
+        traffic.waiting -= 1;
+        return Ok(value);
+      },
+      Err(RecvTimeoutError::Disconnected) => NetworkErrorKind::InputClosed,
+      Err(RecvTimeoutError::Timeout) if traffic.waiting == traffic.running && traffic.in_flight == 0 => {
+        NetworkErrorKind::Deadlock
+      },
+      Err(RecvTimeoutError::Timeout) => continue
+    };
+
+    traffic.waiting -= 1;
+    return Err(NetworkError { node, kind });
+  }
+}
+
+// drives a single machine on its own thread, blocking on its channel whenever it needs input
+fn run_node(
+  node: usize,
+  machine: Machine,
+  input: Receiver<i64>,
+  successors: Vec<Sender<i64>>,
+  traffic: &Mutex<Traffic>
+) -> Result<Vec<i64>, NetworkError> {
+  let result = drive(node, machine, &input, &successors, traffic);
+
+  // whatever is still queued for this node will never be read
+  let mut traffic = traffic.lock().unwrap();
+  traffic.in_flight -= input.try_iter().count();
+  drop(input);
+  traffic.running -= 1;
+
+  result
+}
+
+fn drive(
+  node: usize,
+  mut machine: Machine,
+  input: &Receiver<i64>,
+  successors: &[Sender<i64>],
+  traffic: &Mutex<Traffic>
+) -> Result<Vec<i64>, NetworkError> {
+  let mut output = Vec::new();
+  loop {
+    let state = machine.execute().map_err(|error| NetworkError {
+      node,
+      kind: NetworkErrorKind::Vm(error)
+    })?;
+
+    match state {
+      State::Output(value) => {
+        output.push(value);
+        // a successor that already halted simply drops the value
+        for successor in successors.iter() {
+          let mut traffic = traffic.lock().unwrap();
+          if successor.send(value).is_ok() {
+            traffic.in_flight += 1;
+          }
+        }
+      },
+      State::NeedsInput => machine.add_input(receive(node, input, traffic)?),
+      State::Halted => return Ok(output),
+      State::BudgetExceeded(pc) => return Err(NetworkError { node, kind: NetworkErrorKind::BudgetExceeded(pc) })
+    }
+  }
+}
+
+impl Network {
+  pub fn new(machines: Vec<Machine>, topology: Topology) -> Result<Self, NetworkError> {
+    if machines.len() != topology.nodes {
+      return Err(NetworkError {
+        node: machines.len().min(topology.nodes),
+        kind: NetworkErrorKind::MachineCount(machines.len())
+      });
+    }
+    topology.validate()?;
+
+    Ok(Self { machines, topology })
+  }
+
+  // gives every machine the same instruction budget, so a looping node cannot hang the network
+  pub fn set_budget(&mut self, budget: Option<u64>) {
+    self.machines.iter_mut().for_each(|machine| machine.set_budget(budget));
+  }
+
+  pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+    self.machines.iter_mut().for_each(|machine| machine.set_deadline(deadline));
+  }
+
+  // one copy of the program per phase setting, with the phase queued as its first input
+  pub fn amplifiers(program: &[i64], phases: &[i64], topology: Topology) -> Result<Self, NetworkError> {
+    let machines = phases
+      .iter()
+      .map(|phase| Machine::new(program.to_vec(), vec![*phase]))
+      .collect();
+
+    Network::new(machines, topology)
+  }
+
+  // feeds the input to the entry node, runs every machine until it halts and returns the
+  // outputs of the exit node
+  pub fn run(self, input: &[i64]) -> Result<Vec<i64>, NetworkError> {
+    let Network { machines, topology } = self;
+    if machines.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let (senders, receivers): (Vec<Sender<i64>>, Vec<Receiver<i64>>) = (0..topology.nodes)
+      .map(|_| mpsc::channel())
+      .unzip();
+
+    for value in input {
+      senders[topology.entry].send(*value).unwrap();
+    }
+    let traffic = Arc::new(Mutex::new(Traffic { running: machines.len(), waiting: 0, in_flight: input.len() }));
+
+    let handles: Vec<thread::JoinHandle<Result<Vec<i64>, NetworkError>>> = machines
+      .into_iter()
+      .zip(receivers)
+      .enumerate()
+      .map(|(node, (machine, receiver))| {
+        let successors = topology
+          .successors(node)
+          .into_iter()
+          .map(|successor| senders[successor].clone())
+          .collect();
+
+        let traffic = Arc::clone(&traffic);
+        thread::spawn(move || run_node(node, machine, receiver, successors, &traffic))
+      })
+      .collect();
+
+    // only the machines hold senders now, so a channel closes once all its writers stop
+    drop(senders);
+
+    let mut results: Vec<Result<Vec<i64>, NetworkError>> = handles
+      .into_iter()
+      .map(|handle| handle.join().expect("Intcode network thread panicked"))
+      .collect();
+
+    // report a machine failure before the input starvation it usually causes downstream
+    let failure = results
+      .iter()
+      .filter_map(|result| result.as_ref().err())
+      .min_by_key(|error| {
+        let starved = matches!(error.kind, NetworkErrorKind::InputClosed | NetworkErrorKind::Deadlock);
+        (starved, error.node)
+      })
+      .cloned();
+    if let Some(error) = failure {
+      return Err(error);
+    }
+
+    results.swap_remove(topology.exit)
+  }
+
+  // same result as `run`, but on the calling thread: every node in turn runs until it blocks on
+  // input or stops, until all of them halted or none can move anymore
+  pub fn run_sequential(self, input: &[i64]) -> Result<Vec<i64>, NetworkError> {
+    let Network { mut machines, topology } = self;
+    if machines.is_empty() {
+      return Ok(Vec::new());
+    }
+
+    let successors: Vec<Vec<usize>> = (0..topology.nodes).map(|node| topology.successors(node)).collect();
+    input.iter().for_each(|value| machines[topology.entry].add_input(*value));
+
+    let mut output = Vec::new();
+    loop {
+      let mut moved = false;
+      for node in 0..machines.len() {
+        if machines[node].is_halted() {
+          continue;
+        }
+
+        let executed = machines[node].instructions_executed();
+        loop {
+          let state = machines[node].execute().map_err(|error| NetworkError {
+            node,
+            kind: NetworkErrorKind::Vm(error)
+          })?;
+
+          match state {
+            State::Output(value) => {
+              if node == topology.exit {
+                output.push(value);
+              }
+              successors[node].iter().for_each(|successor| machines[*successor].add_input(value));
+            },
+            State::NeedsInput | State::Halted => break,
+            State::BudgetExceeded(pc) => return Err(NetworkError { node, kind: NetworkErrorKind::BudgetExceeded(pc) })
+          }
+        }
+        moved |= machines[node].instructions_executed() != executed || machines[node].is_halted();
+      }
+
+      // when nobody ran, every node left waits on input only another waiting node could send
+      let stuck = machines.iter().position(|machine| !machine.is_halted());
+      match stuck {
+        None => return Ok(output),
+        Some(node) if !moved => {
+          let mut predecessors = topology.edges.iter().filter(|(_, to)| *to == node).map(|(from, _)| *from);
+          let kind = if predecessors.all(|from| machines[from].is_halted()) {
+            NetworkErrorKind::InputClosed
+          } else {
+            NetworkErrorKind::Deadlock
+          };
+          return Err(NetworkError { node, kind });
+        },
+        Some(_) => {}
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+
+  fn doubler() -> Vec<i64> {
+    assemble("
+      loop: in  [value]
+            mul [value], #2, [value]
+            out [value]
+            jt  #1, #loop
+      value: .data 0
+    ").unwrap()
+  }
+
+  #[test]
+  fn test_chain() {
+    let machines = (0..3).map(|_| Machine::new(assemble("in [9]\nmul [9], #2, [9]\nout [9]\nhlt").unwrap(), Vec::new())).collect();
+    let network = Network::new(machines, Topology::chain(3)).unwrap();
+
+    assert_eq!(network.run(&[5]).unwrap(), vec![40]);
+  }
+
+  #[test]
+  fn test_fan_out() {
+    let program = assemble("in [7]\nout [7]\nout [7]\nhlt").unwrap();
+    let machines = (0..3).map(|_| Machine::new(program.clone(), Vec::new())).collect();
+
+    let mut topology = Topology::new(3);
+    topology.connect(0, 1).connect(0, 2);
+    let network = Network::new(machines, topology).unwrap();
+
+    assert_eq!(network.run(&[3]).unwrap(), vec![3, 3]);
+  }
+
+  #[test]
+  fn test_input_closed() {
+    // the second doubler keeps asking for input after the first one ran dry
+    let machines = vec![
+      Machine::new(assemble("in [5]\nout [5]\nhlt").unwrap(), Vec::new()),
+      Machine::new(doubler(), Vec::new())
+    ];
+    let network = Network::new(machines, Topology::chain(2)).unwrap();
+
+    assert_eq!(network.run(&[1]).unwrap_err(), NetworkError {
+      node: 1,
+      kind: NetworkErrorKind::InputClosed
+    });
+  }
+
+  #[test]
+  fn test_vm_errors() {
+    let machines = vec![
+      Machine::new(vec![42], Vec::new()),
+      Machine::new(doubler(), Vec::new())
+    ];
+    let error = Network::new(machines, Topology::chain(2)).unwrap().run(&[]).unwrap_err();
+
+    assert_eq!(error.node, 0);
+    assert!(matches!(error.kind, NetworkErrorKind::Vm(_)));
+  }
+
+  #[test]
+  fn test_budget() {
+    // the second node spins forever once it has its input
+    let machines = vec![
+      Machine::new(doubler(), Vec::new()),
+      Machine::new(assemble("in [9]\nloop: jt #1, #loop").unwrap(), Vec::new())
+    ];
+    let mut network = Network::new(machines, Topology::chain(2)).unwrap();
+    network.set_budget(Some(1000));
+
+    assert_eq!(network.run(&[1]).unwrap_err(), NetworkError {
+      node: 1,
+      kind: NetworkErrorKind::BudgetExceeded(2)
+    });
+  }
+
+  #[test]
+  fn test_deadlock() {
+    // both nodes want a second value, the ring keeps their channels open forever
+    let machines = (0..2).map(|_| Machine::new(assemble("in [5]\nin [5]\nhlt").unwrap(), Vec::new())).collect();
+    let error = Network::new(machines, Topology::ring(2)).unwrap().run(&[1]).unwrap_err();
+
+    assert_eq!(error.kind, NetworkErrorKind::Deadlock);
+  }
+
+  #[test]
+  fn test_sequential() {
+    let chain = |count: usize| -> Vec<Machine> {
+      (0..count).map(|_| Machine::new(assemble("in [9]\nmul [9], #2, [9]\nout [9]\nhlt").unwrap(), Vec::new())).collect()
+    };
+    let network = Network::new(chain(3), Topology::chain(3)).unwrap();
+    assert_eq!(network.run_sequential(&[5]).unwrap(), vec![40]);
+
+    let machines = vec![
+      Machine::new(assemble("in [5]\nout [5]\nhlt").unwrap(), Vec::new()),
+      Machine::new(doubler(), Vec::new())
+    ];
+    let error = Network::new(machines, Topology::chain(2)).unwrap().run_sequential(&[1]).unwrap_err();
+    assert_eq!(error, NetworkError { node: 1, kind: NetworkErrorKind::InputClosed });
+
+    let machines = (0..2).map(|_| Machine::new(assemble("in [5]\nin [5]\nhlt").unwrap(), Vec::new())).collect();
+    let error = Network::new(machines, Topology::ring(2)).unwrap().run_sequential(&[1]).unwrap_err();
+    assert_eq!(error.kind, NetworkErrorKind::Deadlock);
+  }
+
+  #[test]
+  fn test_no_such_node() {
+    let mut topology = Topology::chain(2);
+    topology.exit = 2;
+    let machines = (0..2).map(|_| Machine::new(doubler(), Vec::new())).collect();
+
+    assert_eq!(Network::new(machines, topology).err(), Some(NetworkError {
+      node: 2,
+      kind: NetworkErrorKind::NoSuchNode
+    }));
+
+    // an edge pushed past `connect` is caught all the same
+    let mut topology = Topology::chain(2);
+    topology.edges.push((1, 7));
+    let machines = (0..2).map(|_| Machine::new(doubler(), Vec::new())).collect();
+    assert_eq!(Network::new(machines, topology).err(), Some(NetworkError {
+      node: 7,
+      kind: NetworkErrorKind::NoSuchNode
+    }));
+
+    let machines = vec![Machine::new(doubler(), Vec::new())];
+    assert_eq!(Network::new(machines, Topology::chain(3)).err(), Some(NetworkError {
+      node: 1,
+      kind: NetworkErrorKind::MachineCount(1)
+    }));
+  }
+}
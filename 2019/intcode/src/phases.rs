@@ -0,0 +1,256 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use crate::network::{Network, NetworkError, Topology};
+
+// permutations a worker claims at a time, large enough to keep the shared counter quiet
+const CHUNK: usize = 16;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchOptions {
+  // worker threads, defaults to the available parallelism
+  pub workers: Option<usize>,
+  // instruction budget for every machine a search runs
+  pub budget: Option<u64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseSetting {
+  pub phases: Vec<i64>,
+  pub signal: i64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhaseError {
+  // every setting failed, this is the failure of the first one
+  Network(NetworkError),
+  TooManyPermutations
+}
+
+impl fmt::Display for PhaseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      PhaseError::Network(error) => write!(f, "{}", error),
+      PhaseError::TooManyPermutations => write!(f, "too many phase permutations to enumerate")
+    }
+  }
+}
+
+impl Error for PhaseError {}
+
+impl From<NetworkError> for PhaseError {
+  fn from(error: NetworkError) -> Self {
+    PhaseError::Network(error)
+  }
+}
+
+// number of ordered selections of k items out of n
+fn permutation_count(n: usize, k: usize) -> Option<usize> {
+  if k > n {
+    return Some(0);
+  }
+
+  ((n - k + 1)..=n).try_fold(1_usize, |total, factor| total.checked_mul(factor))
+}
+
+// the rank-th k-permutation of items in lexicographic order of item positions
+fn unrank(items: &[i64], k: usize, mut rank: usize) -> Vec<i64> {
+  let mut available = items.to_vec();
+  let mut permutation = Vec::with_capacity(k);
+
+  for position in 0..k {
+    let block = permutation_count(available.len() - 1, k - position - 1).unwrap();
+    permutation.push(available.remove(rank / block));
+    rank %= block;
+  }
+
+  permutation
+}
+
+// the final signal of one setting, run on the calling thread since the search already keeps
+// every core busy
+fn evaluate(
+  program: &[i64],
+  phases: &[i64],
+  topology: &Topology,
+  budget: Option<u64>
+) -> Result<Option<i64>, NetworkError> {
+  let mut network = Network::amplifiers(program, phases, topology.clone())?;
+  network.set_budget(budget);
+
+  Ok(network.run_sequential(&[0])?.pop())
+}
+
+// tries every ordering of the phase set across the network nodes (one phase per node, without
+// reuse) on worker threads and returns the setting producing the highest final signal. Settings
+// that fail are skipped, the search only fails when all of them did
+pub fn best_phase_setting(
+  program: &[i64],
+  phases: &[i64],
+  topology: &Topology,
+  options: &SearchOptions
+) -> Result<Option<PhaseSetting>, PhaseError> {
+  topology.validate()?;
+
+  let total = permutation_count(phases.len(), topology.nodes).ok_or(PhaseError::TooManyPermutations)?;
+  let workers = options.workers
+    .unwrap_or_else(|| thread::available_parallelism().map(|count| count.get()).unwrap_or(1))
+    .max(1);
+
+  let next = AtomicUsize::new(0);
+  // best result as (signal, rank), the lowest rank wins ties so results are deterministic
+  let best: Mutex<Option<(i64, usize)>> = Mutex::new(None);
+  let failure: Mutex<Option<(usize, NetworkError)>> = Mutex::new(None);
+
+  thread::scope(|scope| {
+    for _ in 0..workers {
+      scope.spawn(|| {
+        let mut local: Option<(i64, usize)> = None;
+
+        loop {
+          let start = next.fetch_add(CHUNK, Ordering::Relaxed);
+          if start >= total {
+            break;
+          }
+
+          for rank in start..(start + CHUNK).min(total) {
+            let setting = unrank(phases, topology.nodes, rank);
+            match evaluate(program, &setting, topology, options.budget) {
+              Ok(Some(signal)) => {
+                if local.map(|(best, _)| signal > best).unwrap_or(true) {
+                  local = Some((signal, rank));
+                }
+              },
+              Ok(None) => {},
+              Err(error) => {
+                let mut failure = failure.lock().unwrap();
+                if failure.as_ref().map(|(first, _)| rank < *first).unwrap_or(true) {
+                  *failure = Some((rank, error));
+                }
+              }
+            }
+          }
+        }
+
+        if let Some((signal, rank)) = local {
+          let mut best = best.lock().unwrap();
+          let better = match *best {
+            Some((current, current_rank)) => signal > current || (signal == current && rank < current_rank),
+            None => true
+          };
+          if better {
+            *best = Some((signal, rank));
+          }
+        }
+      });
+    }
+  });
+
+  let best = best.into_inner().unwrap().map(|(signal, rank)| PhaseSetting {
+    phases: unrank(phases, topology.nodes, rank),
+    signal
+  });
+  match (best, failure.into_inner().unwrap()) {
+    (None, Some((_, error))) => Err(PhaseError::Network(error)),
+    (best, _) => Ok(best)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::network::NetworkErrorKind;
+
+  fn workers(workers: usize) -> SearchOptions {
+    SearchOptions {
+      workers: Some(workers),
+      budget: None
+    }
+  }
+
+  fn parse(program: &str) -> Vec<i64> {
+    program
+      .split(',')
+      .map(|el| el.parse().unwrap())
+      .collect()
+  }
+
+  #[test]
+  fn test_unrank() {
+    let permutations: Vec<Vec<i64>> = (0..6).map(|rank| unrank(&[1, 2, 3], 2, rank)).collect();
+
+    assert_eq!(permutations, vec![
+      vec![1, 2], vec![1, 3], vec![2, 1], vec![2, 3], vec![3, 1], vec![3, 2]
+    ]);
+    assert_eq!(permutation_count(10, 10), Some(3628800));
+    assert_eq!(permutation_count(3, 4), Some(0));
+  }
+
+  #[test]
+  fn test_chain() {
+    let program = parse("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0");
+    let best = best_phase_setting(&program, &[0, 1, 2, 3, 4], &Topology::chain(5), &workers(3)).unwrap();
+
+    assert_eq!(best, Some(PhaseSetting {
+      phases: vec![4, 3, 2, 1, 0],
+      signal: 43210
+    }));
+  }
+
+  #[test]
+  fn test_ring() {
+    let program = parse("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5");
+    let best = best_phase_setting(&program, &[5, 6, 7, 8, 9], &Topology::ring(5), &SearchOptions::default()).unwrap().unwrap();
+
+    assert_eq!(best.phases, vec![9, 8, 7, 6, 5]);
+    assert_eq!(best.signal, 139629729);
+  }
+
+  #[test]
+  fn test_larger_phase_set() {
+    // each amplifier adds its phase, so the best pick is the three largest phases in any order
+    let program = parse("3,11,3,12,1,11,12,11,4,11,99,0,0");
+    let best = best_phase_setting(&program, &[1, 7, 3, 9, 5], &Topology::chain(3), &workers(4)).unwrap().unwrap();
+
+    assert_eq!(best.signal, 21);
+    assert_eq!(best.phases, vec![7, 9, 5]);
+  }
+
+  #[test]
+  fn test_errors() {
+    let error = best_phase_setting(&[3, 0, 42], &[0, 1], &Topology::chain(2), &workers(2)).unwrap_err();
+    assert!(matches!(error, PhaseError::Network(NetworkError { node: 0, kind: NetworkErrorKind::Vm(_) })));
+
+    // both amplifiers wait for a third value in the ring
+    let error = best_phase_setting(&parse("3,9,3,9,3,9,4,9,99,0"), &[0, 1], &Topology::ring(2), &workers(1)).unwrap_err();
+    assert_eq!(error, PhaseError::Network(NetworkError { node: 0, kind: NetworkErrorKind::Deadlock }));
+
+    let mut topology = Topology::chain(2);
+    topology.entry = 5;
+    let error = best_phase_setting(&[99], &[0, 1], &topology, &workers(1)).unwrap_err();
+    assert_eq!(error, PhaseError::Network(NetworkError { node: 5, kind: NetworkErrorKind::NoSuchNode }));
+
+    let mut topology = Topology::chain(2);
+    topology.edges.push((0, 3));
+    let error = best_phase_setting(&[99], &[0, 1], &topology, &workers(1)).unwrap_err();
+    assert_eq!(error, PhaseError::Network(NetworkError { node: 3, kind: NetworkErrorKind::NoSuchNode }));
+  }
+
+  #[test]
+  fn test_budget() {
+    // phase 1 makes the amplifier spin forever
+    let program = parse("3,14,3,15,1008,14,1,16,1005,16,8,4,15,99,0,0,0");
+    let options = SearchOptions {
+      workers: Some(2),
+      budget: Some(10000)
+    };
+    let error = best_phase_setting(&program, &[0, 1], &Topology::chain(2), &options).unwrap_err();
+    assert_eq!(error, PhaseError::Network(NetworkError { node: 1, kind: NetworkErrorKind::BudgetExceeded(8) }));
+
+    // settings without phase 1 still finish and win
+    let best = best_phase_setting(&program, &[0, 1, 2], &Topology::chain(2), &options).unwrap();
+    assert_eq!(best, Some(PhaseSetting { phases: vec![0, 2], signal: 0 }));
+  }
+}
@@ -0,0 +1,291 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use crate::error::VmError;
+use crate::machine::{Machine, State};
+use crate::phases::SearchOptions;
+use crate::snapshot::Snapshot;
+
+// candidates a worker claims at a time
+const CHUNK: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+  // a memory cell overwritten before the run, noun/verb style
+  Memory(usize),
+  // a value queued as input after whatever the snapshot already holds. The index only orders
+  // the input slots among each other, lowest first, so gaps are fine and equal indices keep the
+  // order of the variables
+  Input(usize)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variable {
+  pub slot: Slot,
+  pub values: RangeInclusive<i64>
+}
+
+// how a candidate run ended, handed to the predicate
+pub struct Outcome {
+  pub output: Vec<i64>,
+  pub machine: Machine,
+  // Halted, NeedsInput or BudgetExceeded, or the error that stopped the machine
+  pub state: Result<State, VmError>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+  // one value per variable, in the order the variables were given
+  pub values: Vec<i64>,
+  pub output: Vec<i64>
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SearchError {
+  // a memory slot at or past the memory limit of the snapshot
+  MemoryLimit(usize),
+  // the combinations, or the values of a single variable, do not fit in a usize
+  TooManyCandidates
+}
+
+impl fmt::Display for SearchError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SearchError::MemoryLimit(address) => write!(f, "memory slot {} is past the memory limit", address),
+      SearchError::TooManyCandidates => write!(f, "too many candidates to enumerate")
+    }
+  }
+}
+
+impl Error for SearchError {}
+
+fn range_len(range: &RangeInclusive<i64>) -> Result<usize, SearchError> {
+  if range.is_empty() {
+    return Ok(0);
+  }
+
+  let len = (*range.end() as i128 - *range.start() as i128 + 1) as u128;
+  usize::try_from(len).map_err(|_| SearchError::TooManyCandidates)
+}
+
+// the rank-th combination, with the first variable changing slowest
+fn unrank(variables: &[Variable], lens: &[usize], mut rank: usize) -> Vec<i64> {
+  let mut values = vec![0; variables.len()];
+  for (index, variable) in variables.iter().enumerate().rev() {
+    values[index] = variable.values.start() + (rank % lens[index]) as i64;
+    rank /= lens[index];
+  }
+
+  values
+}
+
+fn run(
+  snapshot: &Snapshot,
+  variables: &[Variable],
+  values: &[i64],
+  budget: Option<u64>
+) -> Result<Outcome, SearchError> {
+  let mut machine = Machine::from_snapshot(snapshot);
+  machine.set_budget(budget);
+
+  let mut inputs: Vec<(usize, i64)> = Vec::new();
+  for (variable, value) in variables.iter().zip(values) {
+    match variable.slot {
+      Slot::Memory(address) => machine.memory.write(address, *value).ok_or(SearchError::MemoryLimit(address))?,
+      Slot::Input(index) => inputs.push((index, *value))
+    }
+  }
+  inputs.sort_by_key(|(index, _)| *index);
+  inputs.iter().for_each(|(_, value)| machine.add_input(*value));
+
+  let mut output = Vec::new();
+  let state = loop {
+    match machine.execute() {
+      Ok(State::Output(value)) => output.push(value),
+      other => break other
+    }
+  };
+
+  Ok(Outcome { output, machine, state })
+}
+
+// tries every combination of variable values, each on a fresh machine restored from the
+// snapshot, and returns the ones the predicate accepts in enumeration order. Failing or
+// runaway candidates are handed to the predicate like any other, so set a budget when some
+// values can make the program loop
+pub fn search<P>(
+  snapshot: &Snapshot,
+  variables: &[Variable],
+  options: &SearchOptions,
+  predicate: P
+) -> Result<Vec<Assignment>, SearchError>
+where
+  P: Fn(&Outcome) -> bool + Sync
+{
+  for variable in variables.iter() {
+    if let Slot::Memory(address) = variable.slot {
+      if snapshot.memory.limit().map(|limit| address >= limit).unwrap_or(false) {
+        return Err(SearchError::MemoryLimit(address));
+      }
+    }
+  }
+
+  let lens = variables
+    .iter()
+    .map(|variable| range_len(&variable.values))
+    .collect::<Result<Vec<usize>, SearchError>>()?;
+  let total = lens
+    .iter()
+    .try_fold(1_usize, |total, len| total.checked_mul(*len))
+    .ok_or(SearchError::TooManyCandidates)?;
+  let workers = options.workers
+    .unwrap_or_else(|| thread::available_parallelism().map(|count| count.get()).unwrap_or(1))
+    .max(1);
+
+  let next = AtomicUsize::new(0);
+  let matches: Mutex<Vec<(usize, Assignment)>> = Mutex::new(Vec::new());
+  let failure: Mutex<Option<SearchError>> = Mutex::new(None);
+
+  thread::scope(|scope| {
+    for _ in 0..workers {
+      scope.spawn(|| {
+        let mut local = Vec::new();
+
+        loop {
+          let start = next.fetch_add(CHUNK, Ordering::Relaxed);
+          if start >= total {
+            break;
+          }
+
+          for rank in start..(start + CHUNK).min(total) {
+            let values = unrank(variables, &lens, rank);
+            let outcome = match run(snapshot, variables, &values, options.budget) {
+              Ok(outcome) => outcome,
+              Err(error) => {
+                *failure.lock().unwrap() = Some(error);
+                return;
+              }
+            };
+            if predicate(&outcome) {
+              local.push((rank, Assignment { values, output: outcome.output }));
+            }
+          }
+        }
+
+        matches.lock().unwrap().extend(local);
+      });
+    }
+  });
+
+  if let Some(error) = failure.into_inner().unwrap() {
+    return Err(error);
+  }
+
+  let mut matches = matches.into_inner().unwrap();
+  matches.sort_unstable_by_key(|(rank, _)| *rank);
+
+  Ok(matches.into_iter().map(|(_, assignment)| assignment).collect())
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::assemble::assemble;
+  use crate::loader::parse;
+
+  fn options(workers: usize, budget: Option<u64>) -> SearchOptions {
+    SearchOptions {
+      workers: Some(workers),
+      budget
+    }
+  }
+
+  #[test]
+  fn test_noun_verb() {
+    let program = parse("1,9,10,3,2,3,11,0,99,30,40,50").unwrap();
+    let snapshot = Machine::new(program, Vec::new()).snapshot();
+    let variables = vec![
+      Variable { slot: Slot::Memory(1), values: 0..=11 },
+      Variable { slot: Slot::Memory(2), values: 0..=11 }
+    ];
+
+    let found = search(&snapshot, &variables, &options(3, None), |outcome| {
+      outcome.state.is_ok() && outcome.machine.memory.read(0) == Some(3500)
+    }).unwrap();
+    let values: Vec<Vec<i64>> = found.into_iter().map(|assignment| assignment.values).collect();
+
+    assert!(values.contains(&vec![9, 10]));
+    assert!(values.contains(&vec![10, 9]));
+  }
+
+  #[test]
+  fn test_inputs_after_snapshot() {
+    // prints the product of two inputs, searched for once the first input is already consumed
+    let program = assemble("
+      in  [a]
+      in  [b]
+      mul [a], [b], [a]
+      out [a]
+      hlt
+      a: .data 0
+      b: .data 0
+    ").unwrap();
+    let mut machine = Machine::new(program, vec![3]);
+    assert_eq!(machine.execute().unwrap(), State::NeedsInput);
+
+    let variables = vec![Variable { slot: Slot::Input(0), values: -20..=20 }];
+    let found = search(&machine.snapshot(), &variables, &options(4, None), |outcome| outcome.output == [36]).unwrap();
+
+    assert_eq!(found, vec![Assignment { values: vec![12], output: vec![36] }]);
+  }
+
+  #[test]
+  fn test_input_order_and_budget() {
+    // loops forever unless the first input is smaller than the second
+    let program = assemble("
+            in  [a]
+            in  [b]
+            lt  [a], [b], [ok]
+      spin: jf  [ok], #spin
+            out [a]
+            hlt
+      a:    .data 0
+      b:    .data 0
+      ok:   .data 0
+    ").unwrap();
+    let snapshot = Machine::new(program, Vec::new()).snapshot();
+    let variables = vec![
+      Variable { slot: Slot::Input(1), values: 1..=3 },
+      Variable { slot: Slot::Input(0), values: 1..=3 }
+    ];
+
+    let found = search(&snapshot, &variables, &options(2, Some(1000)), |outcome| {
+      outcome.state == Ok(State::Halted)
+    }).unwrap();
+    let values: Vec<Vec<i64>> = found.into_iter().map(|assignment| assignment.values).collect();
+
+    assert_eq!(values, vec![vec![2, 1], vec![3, 1], vec![3, 2]]);
+    assert_eq!(unrank(&variables, &[3, 3], 5), vec![2, 3]);
+  }
+
+  #[test]
+  fn test_errors() {
+    let mut machine = Machine::new(vec![99], Vec::new());
+    machine.memory.set_limit(Some(4));
+    let snapshot = machine.snapshot();
+    let search = |variables: Vec<Variable>| search(&snapshot, &variables, &options(2, None), |_| true);
+
+    assert_eq!(range_len(&(i64::MIN..=i64::MAX)), Err(SearchError::TooManyCandidates));
+    assert_eq!(range_len(&(i64::MIN..=-1)), Ok(1 << 63));
+    assert_eq!(search(vec![Variable { slot: Slot::Memory(4), values: 0..=1 }]), Err(SearchError::MemoryLimit(4)));
+    assert_eq!(search(vec![
+      Variable { slot: Slot::Input(0), values: i64::MIN..=-1 },
+      Variable { slot: Slot::Input(1), values: 0..=1 }
+    ]), Err(SearchError::TooManyCandidates));
+    assert_eq!(search(vec![Variable { slot: Slot::Input(0), values: RangeInclusive::new(1, 0) }]), Ok(Vec::new()));
+  }
+}
@@ -0,0 +1,318 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use crate::machine::Machine;
+use crate::memory::Memory;
+
+const MAGIC: &[u8; 4] = b"ICS2";
+
+#[derive(Debug)]
+pub enum SnapshotError {
+  Io(io::Error),
+  Format(String)
+}
+
+impl fmt::Display for SnapshotError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      SnapshotError::Io(error) => write!(f, "{}", error),
+      SnapshotError::Format(message) => write!(f, "invalid snapshot: {}", message)
+    }
+  }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+  fn from(error: io::Error) -> Self {
+    SnapshotError::Io(error)
+  }
+}
+
+// everything needed to resume a machine exactly where it stopped. Clones share the memory, only
+// a machine restored from a snapshot gets its own copy to write to
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+  pub pc: usize,
+  pub memory: Arc<Memory>,
+  pub input: Vec<i64>,
+  pub relative_offset: i64,
+  pub halted: bool
+}
+
+impl Machine {
+  pub fn snapshot(&self) -> Snapshot {
+    Snapshot {
+      pc: self.pc,
+      memory: Arc::new(self.memory.clone()),
+      input: self.input.iter().copied().collect(),
+      relative_offset: self.relative_offset,
+      halted: self.halted
+    }
+  }
+
+  pub fn from_snapshot(snapshot: &Snapshot) -> Self {
+    let mut machine = Machine::new(Vec::new(), snapshot.input.clone());
+    machine.pc = snapshot.pc;
+    machine.memory = Memory::clone(&snapshot.memory);
+    machine.relative_offset = snapshot.relative_offset;
+    machine.halted = snapshot.halted;
+
+    machine
+  }
+}
+
+// values are stored as zigzag encoded LEB128 varints, which keeps typical programs tiny
+fn write_varint(bytes: &mut Vec<u8>, value: i64) {
+  write_unsigned(bytes, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+fn write_unsigned(bytes: &mut Vec<u8>, mut value: u64) {
+  loop {
+    let byte = (value & 0x7f) as u8;
+    value >>= 7;
+    if value == 0 {
+      bytes.push(byte);
+      return;
+    }
+    bytes.push(byte | 0x80);
+  }
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  position: usize
+}
+
+impl<'a> Reader<'a> {
+  fn varint(&mut self) -> Result<i64, SnapshotError> {
+    let value = self.unsigned()?;
+    Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+  }
+
+  fn unsigned(&mut self) -> Result<u64, SnapshotError> {
+    let mut value: u64 = 0;
+    let mut shift = 0;
+    loop {
+      let byte = *self.bytes
+        .get(self.position)
+        .ok_or_else(|| SnapshotError::Format(String::from("unexpected end of data")))?;
+      self.position += 1;
+
+      if shift >= 64 {
+        return Err(SnapshotError::Format(format!("varint too long at byte {}", self.position)));
+      }
+      value |= ((byte & 0x7f) as u64) << shift;
+      shift += 7;
+
+      if byte & 0x80 == 0 {
+        return Ok(value);
+      }
+    }
+  }
+
+  fn count(&mut self, what: &str) -> Result<usize, SnapshotError> {
+    let value = self.varint()?;
+    // every value takes at least a byte, so a count can never exceed what is left
+    if value < 0 || value as usize > self.bytes.len() - self.position {
+      return Err(SnapshotError::Format(format!("invalid {} count {}", what, value)));
+    }
+
+    Ok(value as usize)
+  }
+
+  fn values(&mut self, count: usize) -> Result<Vec<i64>, SnapshotError> {
+    (0..count).map(|_| self.varint()).collect()
+  }
+}
+
+impl Snapshot {
+  pub fn to_bytes(&self) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+
+    write_varint(&mut bytes, self.pc as i64);
+    write_varint(&mut bytes, self.relative_offset);
+    write_varint(&mut bytes, self.halted as i64);
+    // a presence flag, then the limit itself so every usize fits
+    match self.memory.limit() {
+      Some(limit) => {
+        bytes.push(1);
+        write_unsigned(&mut bytes, limit as u64);
+      },
+      None => bytes.push(0)
+    }
+
+    write_varint(&mut bytes, self.input.len() as i64);
+    self.input.iter().for_each(|value| write_varint(&mut bytes, *value));
+
+    let dense = self.memory.as_slice();
+    write_varint(&mut bytes, dense.len() as i64);
+    dense.iter().for_each(|value| write_varint(&mut bytes, *value));
+
+    let paged = self.memory.paged_cells();
+    write_varint(&mut bytes, paged.len() as i64);
+    for (address, value) in paged {
+      write_varint(&mut bytes, address as i64);
+      write_varint(&mut bytes, value);
+    }
+
+    bytes
+  }
+
+  pub fn from_bytes(bytes: &[u8]) -> Result<Snapshot, SnapshotError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+      return Err(SnapshotError::Format(String::from("missing snapshot header")));
+    }
+
+    let mut reader = Reader { bytes, position: MAGIC.len() };
+    let pc = reader.varint()?;
+    if pc < 0 {
+      return Err(SnapshotError::Format(format!("invalid pc {}", pc)));
+    }
+    let relative_offset = reader.varint()?;
+    let halted = reader.varint()? != 0;
+    let limit = match reader.unsigned()? {
+      0 => None,
+      1 => {
+        let limit = reader.unsigned()?;
+        Some(usize::try_from(limit).map_err(|_| SnapshotError::Format(format!("invalid memory limit {}", limit)))?)
+      },
+      flag => return Err(SnapshotError::Format(format!("invalid memory limit flag {}", flag)))
+    };
+
+    let count = reader.count("input")?;
+    let input = reader.values(count)?;
+
+    let count = reader.count("memory")?;
+    let mut memory = Memory::new(reader.values(count)?);
+    for _ in 0..reader.count("paged memory")? {
+      let address = reader.varint()?;
+      let value = reader.varint()?;
+      if address < 0 {
+        return Err(SnapshotError::Format(format!("invalid address {}", address)));
+      }
+      memory.write(address as usize, value);
+    }
+    memory.set_limit(limit);
+
+    if reader.position != bytes.len() {
+      return Err(SnapshotError::Format(String::from("trailing data after snapshot")));
+    }
+
+    Ok(Snapshot {
+      pc: pc as usize,
+      memory: Arc::new(memory),
+      input,
+      relative_offset,
+      halted
+    })
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SnapshotError> {
+    fs::write(path, self.to_bytes())?;
+    Ok(())
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, SnapshotError> {
+    Snapshot::from_bytes(&fs::read(path)?)
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::machine::State;
+
+  // reads two numbers and prints their sum and product, with the relative base in play
+  fn program() -> Vec<i64> {
+    vec![109, 20, 203, 10, 203, 11, 22201, 10, 11, 12, 204, 12, 22202, 10, 11, 12, 204, 12, 99]
+  }
+
+  #[test]
+  fn test_branching() {
+    let mut machine = Machine::new(program(), vec![6]);
+    assert_eq!(machine.execute().unwrap(), State::NeedsInput);
+    let snapshot = machine.snapshot();
+
+    let results: Vec<Vec<i64>> = (1..=3)
+      .map(|value| {
+        let mut machine = Machine::from_snapshot(&snapshot);
+        machine.add_input(value);
+        machine.run().unwrap()
+      })
+      .collect();
+
+    assert_eq!(results, vec![vec![7, 6], vec![8, 12], vec![9, 18]]);
+  }
+
+  #[test]
+  fn test_round_trip() {
+    let mut machine = Machine::new(program(), vec![4]);
+    machine.execute().unwrap();
+    machine.memory.write(5_000_000_000, -12).unwrap();
+    machine.set_memory_limit(Some(10_000_000_000));
+    machine.add_input(9);
+
+    let bytes = machine.snapshot().to_bytes();
+    let mut restored = Machine::from_snapshot(&Snapshot::from_bytes(&bytes).unwrap());
+
+    assert_eq!(restored.pc, machine.pc);
+    assert_eq!(restored.relative_offset, 20);
+    assert_eq!(restored.memory.read(5_000_000_000), Some(-12));
+    assert_eq!(restored.memory.limit(), Some(10_000_000_000));
+    assert_eq!(restored.run().unwrap(), machine.run().unwrap());
+    assert!(restored.is_halted());
+  }
+
+  #[test]
+  fn test_memory_limits() {
+    for limit in [None, Some(0), Some(i64::MAX as usize), Some(1 << 63), Some(usize::MAX)].iter() {
+      let mut machine = Machine::new(program(), Vec::new());
+      machine.set_memory_limit(*limit);
+
+      let snapshot = Snapshot::from_bytes(&machine.snapshot().to_bytes()).unwrap();
+      assert_eq!(snapshot.memory.limit(), *limit);
+    }
+  }
+
+  #[test]
+  fn test_shared_memory() {
+    let snapshot = Machine::new(program(), vec![1, 2]).snapshot();
+    let copy = snapshot.clone();
+    assert!(Arc::ptr_eq(&snapshot.memory, &copy.memory));
+
+    // the restored machine writes to its own memory
+    let mut machine = Machine::from_snapshot(&copy);
+    machine.run().unwrap();
+    assert_eq!(snapshot.memory.read(32), Some(0));
+    assert_eq!(machine.memory.read(32), Some(2));
+  }
+
+  #[test]
+  fn test_save_and_load() {
+    let path = std::env::temp_dir().join(format!("intcode-snapshot-{}.bin", std::process::id()));
+    let mut machine = Machine::new(program(), vec![2, 3]);
+    machine.run().unwrap();
+
+    machine.snapshot().save(&path).unwrap();
+    let snapshot = Snapshot::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert!(snapshot.halted);
+    assert_eq!(snapshot.memory.read(32), Some(6));
+  }
+
+  #[test]
+  fn test_invalid_data() {
+    assert!(Snapshot::from_bytes(b"nope").is_err());
+    assert!(Snapshot::from_bytes(b"ICS1\x02").is_err());
+
+    let mut bytes = Machine::new(program(), Vec::new()).snapshot().to_bytes();
+    bytes.push(0);
+    assert!(Snapshot::from_bytes(&bytes).is_err());
+  }
+}
@@ -0,0 +1,237 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Write};
+use crate::instruction::{self, Opcode};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+  pub pc: usize,
+  pub instruction: i64,
+  // resolved values of every parameter the instruction reads
+  pub operands: Vec<i64>,
+  // addresses those values came from, immediates left out
+  pub reads: Vec<usize>,
+  // address and value of the write, if the instruction wrote to memory
+  pub write: Option<(usize, i64)>
+}
+
+impl TraceEvent {
+  pub fn opcode(&self) -> i64 {
+    instruction::get_opcode(self.instruction)
+  }
+}
+
+fn opcode_name(opcode: i64) -> String {
+  match Opcode::from_code(opcode) {
+    Some(opcode) => String::from(opcode.mnemonic()),
+    None => format!("?{}", opcode)
+  }
+}
+
+impl fmt::Display for TraceEvent {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let operands: Vec<String> = self.operands.iter().map(|el| el.to_string()).collect();
+    write!(f, "{:>6}: {}", self.pc, opcode_name(self.opcode()))?;
+    if !operands.is_empty() {
+      write!(f, " {}", operands.join(", "))?;
+    }
+    if let Some((address, value)) = self.write {
+      write!(f, " -> [{}] = {}", address, value)?;
+    }
+
+    Ok(())
+  }
+}
+
+pub trait Tracer {
+  fn trace(&mut self, event: &TraceEvent);
+}
+
+// keeps only the most recent events, which is usually all that matters when a run goes wrong
+pub struct RingBuffer {
+  capacity: usize,
+  events: VecDeque<TraceEvent>
+}
+
+impl RingBuffer {
+  pub fn new(capacity: usize) -> Self {
+    Self {
+      capacity,
+      events: VecDeque::with_capacity(capacity)
+    }
+  }
+
+  pub fn events(&self) -> Vec<TraceEvent> {
+    self.events.iter().cloned().collect()
+  }
+}
+
+impl Tracer for RingBuffer {
+  fn trace(&mut self, event: &TraceEvent) {
+    if self.capacity == 0 {
+      return;
+    }
+    if self.events.len() == self.capacity {
+      self.events.pop_front();
+    }
+    self.events.push_back(event.clone());
+  }
+}
+
+// writes one line per instruction, so two runs can be compared with any diff tool
+pub struct WriteTracer<W: Write> {
+  writer: W,
+  error: Option<io::Error>
+}
+
+impl<W: Write> WriteTracer<W> {
+  pub fn new(writer: W) -> Self {
+    Self {
+      writer,
+      error: None
+    }
+  }
+
+  // hands back the writer, or the first error hit while tracing
+  pub fn finish(mut self) -> io::Result<W> {
+    if let Some(error) = self.error {
+      return Err(error);
+    }
+    self.writer.flush()?;
+
+    Ok(self.writer)
+  }
+}
+
+impl<W: Write> Tracer for WriteTracer<W> {
+  fn trace(&mut self, event: &TraceEvent) {
+    if self.error.is_none() {
+      if let Err(error) = writeln!(self.writer, "{}", event) {
+        self.error = Some(error);
+      }
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+  pub instructions: u64,
+  pub opcodes: HashMap<i64, u64>,
+  pub pcs: HashMap<usize, u64>
+}
+
+impl Profiler {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  // most executed addresses first, ties broken by address
+  pub fn hottest(&self, count: usize) -> Vec<(usize, u64)> {
+    let mut pcs: Vec<(usize, u64)> = self.pcs.iter().map(|(pc, hits)| (*pc, *hits)).collect();
+    pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    pcs.truncate(count);
+
+    pcs
+  }
+
+  pub fn summary(&self, count: usize) -> String {
+    let mut lines = vec![format!("instructions executed: {}", self.instructions)];
+
+    let mut opcodes: Vec<(i64, u64)> = self.opcodes.iter().map(|(op, hits)| (*op, *hits)).collect();
+    opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    lines.push(String::from("opcodes:"));
+    for (opcode, hits) in opcodes {
+      lines.push(format!("  {:<4}{:>12}", opcode_name(opcode), hits));
+    }
+
+    lines.push(String::from("hottest pcs:"));
+    for (pc, hits) in self.hottest(count) {
+      lines.push(format!("  {:<6}{:>10}", pc, hits));
+    }
+
+    lines.join("\n")
+  }
+}
+
+impl Tracer for Profiler {
+  fn trace(&mut self, event: &TraceEvent) {
+    self.instructions += 1;
+    *self.opcodes.entry(event.opcode()).or_insert(0) += 1;
+    *self.pcs.entry(event.pc).or_insert(0) += 1;
+  }
+}
+
+// index of the first event where two traces disagree, None when they are identical
+pub fn first_divergence(a: &[TraceEvent], b: &[TraceEvent]) -> Option<usize> {
+  match a.iter().zip(b.iter()).position(|(x, y)| x != y) {
+    Some(index) => Some(index),
+    None if a.len() != b.len() => Some(a.len().min(b.len())),
+    None => None
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::machine::Machine;
+
+  fn countdown() -> Vec<i64> {
+    // counts [10] down from 3, printing each value
+    vec![4, 10, 1001, 10, -1, 10, 1005, 10, 0, 99, 3]
+  }
+
+  #[test]
+  fn test_ring_buffer() {
+    let mut tracer = RingBuffer::new(3);
+    let mut machine = Machine::new(countdown(), Vec::new());
+
+    assert_eq!(machine.run_traced(&mut tracer).unwrap(), vec![3, 2, 1]);
+    let events = tracer.events();
+    assert_eq!(events.len(), 3);
+    assert_eq!(events[0], TraceEvent {
+      pc: 2,
+      instruction: 1001,
+      operands: vec![1, -1],
+      reads: vec![10],
+      write: Some((10, 0))
+    });
+    assert_eq!(events[1].operands, vec![0, 0]);
+    assert_eq!(events[2].pc, 9);
+  }
+
+  #[test]
+  fn test_write_tracer() {
+    let mut tracer = WriteTracer::new(Vec::new());
+    let mut machine = Machine::new(vec![1101, 2, 3, 5, 99, 0], Vec::new());
+    machine.run_traced(&mut tracer).unwrap();
+
+    let text = String::from_utf8(tracer.finish().unwrap()).unwrap();
+    assert_eq!(text, "     0: add 2, 3 -> [5] = 5\n     4: hlt\n");
+  }
+
+  #[test]
+  fn test_profiler() {
+    let mut profiler = Profiler::new();
+    let mut machine = Machine::new(countdown(), Vec::new());
+    machine.run_traced(&mut profiler).unwrap();
+
+    assert_eq!(profiler.instructions, 10);
+    assert_eq!(profiler.opcodes[&4], 3);
+    assert_eq!(profiler.hottest(2), vec![(0, 3), (2, 3)]);
+    assert!(profiler.summary(1).starts_with("instructions executed: 10\nopcodes:\n  add"));
+  }
+
+  #[test]
+  fn test_divergence() {
+    let mut a = RingBuffer::new(100);
+    let mut b = RingBuffer::new(100);
+    Machine::new(countdown(), Vec::new()).run_traced(&mut a).unwrap();
+
+    let mut program = countdown();
+    program[10] = 2;
+    Machine::new(program, Vec::new()).run_traced(&mut b).unwrap();
+
+    assert_eq!(first_divergence(&a.events(), &a.events()), None);
+    assert_eq!(first_divergence(&a.events(), &b.events()), Some(0));
+  }
+}
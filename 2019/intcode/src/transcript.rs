@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use crate::error::VmError;
+use crate::machine::{Machine, State};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Entry {
+  Input { pc: usize, value: i64 },
+  Output { pc: usize, value: i64 }
+}
+
+impl fmt::Display for Entry {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Entry::Input { pc, value } => write!(f, "in {} {}", pc, value),
+      Entry::Output { pc, value } => write!(f, "out {} {}", pc, value)
+    }
+  }
+}
+
+#[derive(Debug)]
+pub enum TranscriptError {
+  Io(io::Error),
+  Format { line: usize, message: String },
+  Vm(VmError),
+  BudgetExceeded(usize),
+  // first entry where the replayed machine went its own way, None on either side means that
+  // side had nothing more to show
+  Mismatch { index: usize, expected: Option<Entry>, actual: Option<Entry> }
+}
+
+fn describe(entry: &Option<Entry>) -> String {
+  entry.map(|entry| entry.to_string()).unwrap_or_else(|| String::from("nothing"))
+}
+
+impl fmt::Display for TranscriptError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      TranscriptError::Io(error) => write!(f, "{}", error),
+      TranscriptError::Format { line, message } => write!(f, "invalid transcript on line {}: {}", line, message),
+      TranscriptError::Vm(error) => write!(f, "{}", error),
+      TranscriptError::BudgetExceeded(pc) => write!(f, "budget exceeded @ {}", pc),
+      TranscriptError::Mismatch { index, expected, actual } => write!(
+        f,
+        "entry {}: expected {}, got {}",
+        index,
+        describe(expected),
+        describe(actual)
+      )
+    }
+  }
+}
+
+impl Error for TranscriptError {}
+
+impl From<io::Error> for TranscriptError {
+  fn from(error: io::Error) -> Self {
+    TranscriptError::Io(error)
+  }
+}
+
+impl From<VmError> for TranscriptError {
+  fn from(error: VmError) -> Self {
+    TranscriptError::Vm(error)
+  }
+}
+
+// every input a machine consumed and every output it produced, in order, one `in <pc> <value>`
+// or `out <pc> <value>` per line when saved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transcript {
+  pub entries: Vec<Entry>
+}
+
+impl fmt::Display for Transcript {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for entry in self.entries.iter() {
+      writeln!(f, "{}", entry)?;
+    }
+
+    Ok(())
+  }
+}
+
+impl FromStr for Transcript {
+  type Err = TranscriptError;
+
+  // blank lines and `#` comments are skipped
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let mut entries = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+      let error = |message: &str| TranscriptError::Format { line: index + 1, message: String::from(message) };
+      let parts: Vec<&str> = line.split('#').next().unwrap().split_whitespace().collect();
+      if parts.is_empty() {
+        continue;
+      }
+      if parts.len() != 3 {
+        return Err(error("expected `in|out <pc> <value>`"));
+      }
+
+      let pc = parts[1].parse().map_err(|_| error("invalid pc"))?;
+      let value = parts[2].parse().map_err(|_| error("invalid value"))?;
+      entries.push(match parts[0] {
+        "in" => Entry::Input { pc, value },
+        "out" => Entry::Output { pc, value },
+        _ => return Err(error("expected `in` or `out`"))
+      });
+    }
+
+    Ok(Transcript { entries })
+  }
+}
+
+impl Transcript {
+  // runs the program with the given input and records what happened
+  pub fn record(program: &[i64], input: &[i64]) -> Result<Transcript, TranscriptError> {
+    let mut machine = Machine::new(program.to_vec(), input.to_vec());
+    machine.start_recording();
+    machine.run()?;
+
+    Ok(machine.take_transcript().unwrap())
+  }
+
+  pub fn inputs(&self) -> Vec<i64> {
+    self.entries
+      .iter()
+      .filter_map(|entry| match entry {
+        Entry::Input { value, .. } => Some(*value),
+        Entry::Output { .. } => None
+      })
+      .collect()
+  }
+
+  pub fn outputs(&self) -> Vec<i64> {
+    self.entries
+      .iter()
+      .filter_map(|entry| match entry {
+        Entry::Output { value, .. } => Some(*value),
+        Entry::Input { .. } => None
+      })
+      .collect()
+  }
+
+  fn check(&self, actual: &[Entry], from: usize) -> Result<(), TranscriptError> {
+    for (index, entry) in actual.iter().enumerate().skip(from) {
+      if self.entries.get(index) != Some(entry) {
+        return Err(TranscriptError::Mismatch {
+          index,
+          expected: self.entries.get(index).copied(),
+          actual: Some(*entry)
+        });
+      }
+    }
+
+    Ok(())
+  }
+
+  // drives the machine with the recorded inputs, handing each one over only when it is asked
+  // for, and fails on the first input or output that differs from the recording. Replaying is
+  // done once the machine halts or asks for input past the end of the recording
+  pub fn replay(&self, mut machine: Machine) -> Result<(), TranscriptError> {
+    machine.start_recording();
+    let mut checked = 0;
+
+    loop {
+      let state = machine.execute()?;
+      let actual = machine.recorded();
+      self.check(actual, checked)?;
+      checked = actual.len();
+
+      match state {
+        State::Output(_) => {},
+        State::NeedsInput => match self.entries.get(checked) {
+          Some(Entry::Input { value, .. }) => machine.add_input(*value),
+          // the recording stopped while the machine was waiting on input as well
+          None => return Ok(()),
+          expected => return Err(TranscriptError::Mismatch { index: checked, expected: expected.copied(), actual: None })
+        },
+        State::Halted if checked == self.entries.len() => return Ok(()),
+        State::Halted => return Err(TranscriptError::Mismatch {
+          index: checked,
+          expected: self.entries.get(checked).copied(),
+          actual: None
+        }),
+        State::BudgetExceeded(pc) => return Err(TranscriptError::BudgetExceeded(pc))
+      }
+    }
+  }
+
+  pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), TranscriptError> {
+    fs::write(path, self.to_string())?;
+    Ok(())
+  }
+
+  pub fn load<P: AsRef<Path>>(path: P) -> Result<Transcript, TranscriptError> {
+    fs::read_to_string(path)?.parse()
+  }
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+  use crate::loader::parse;
+
+  // day 5: prints 999, 1000 or 1001 for an input below, equal to or above 8
+  const COMPARE: &str = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,\
+    1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+  // day 7: one amplifier of the feedback loop
+  const AMPLIFIER: &str = "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,\
+    1005,28,6,99,0,0,5";
+  // day 9: prints a copy of itself
+  const QUINE: &str = "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99";
+
+  #[test]
+  fn test_record() {
+    let transcript = Transcript::record(&parse(COMPARE).unwrap(), &[8]).unwrap();
+    assert_eq!(transcript.to_string(), "in 0 8\nout 26 1000\n");
+
+    let transcript = Transcript::record(&parse(AMPLIFIER).unwrap(), &[9, 0, 3, 5]).unwrap();
+    assert_eq!(transcript.to_string(), "in 0 9\nin 6 0\nout 16 5\nin 6 3\nout 16 11\nin 6 5\nout 16 15\n");
+    assert_eq!(transcript.inputs(), vec![9, 0, 3, 5]);
+
+    let quine = parse(QUINE).unwrap();
+    assert_eq!(Transcript::record(&quine, &[]).unwrap().outputs(), quine);
+  }
+
+  #[test]
+  fn test_replay() {
+    for (source, input) in [(COMPARE, vec![7]), (AMPLIFIER, vec![5, 1, 2, 3]), (QUINE, vec![])].iter() {
+      let program = parse(source).unwrap();
+      let transcript = Transcript::record(&program, input).unwrap();
+      let text = transcript.to_string();
+
+      text.parse::<Transcript>().unwrap().replay(Machine::new(program, Vec::new())).unwrap();
+    }
+  }
+
+  #[test]
+  fn test_mismatch() {
+    let transcript: Transcript = "# compare with 8\nin 0 8\nout 26 1000\n".parse().unwrap();
+
+    // a VM that gets equality wrong takes the less than branch instead
+    let mut program = parse(COMPARE).unwrap();
+    program[2] = 1007;
+    let error = transcript.replay(Machine::new(program, Vec::new())).unwrap_err();
+    assert_eq!(error.to_string(), "entry 1: expected out 26 1000, got out 31 999");
+
+    let error = transcript.replay(Machine::new(parse("3,0,99").unwrap(), Vec::new())).unwrap_err();
+    assert_eq!(error.to_string(), "entry 1: expected out 26 1000, got nothing");
+
+    assert!(matches!("in 0".parse::<Transcript>(), Err(TranscriptError::Format { line: 1, .. })));
+  }
+
+  #[test]
+  fn test_save_and_load() {
+    let path = std::env::temp_dir().join(format!("intcode-transcript-{}.txt", std::process::id()));
+    let transcript = Transcript::record(&parse(COMPARE).unwrap(), &[9]).unwrap();
+
+    transcript.save(&path).unwrap();
+    let loaded = Transcript::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded, transcript);
+  }
+}
@@ -0,0 +1,324 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::disassemble::find_code;
+use crate::instruction::{Instruction, Opcode, Parameter, ParameterMode};
+use crate::loader::{self, LoadError};
+
+const PRELUDE: &str = "\
+use intcode::{Machine, State, VmError};
+
+#[allow(unused_macros)]
+macro_rules! or_interpret {
+  ($machine:expr, $interpreted:expr, $value:expr) => {
+    match $value {
+      Some(value) => value,
+      None => return interpret($machine, $interpreted)
+    }
+  };
+}
+
+// runs the interpreter until it has something to report, noting any write into compiled code
+// on the way so later calls stop trusting the compiled arms
+fn interpret(machine: &mut Machine, interpreted: &mut bool) -> Result<State, VmError> {
+  loop {
+    let state = machine.step()?;
+    if machine.last_write().map(is_code).unwrap_or(false) {
+      *interpreted = true;
+    }
+    if let Some(state) = state {
+      return Ok(state);
+    }
+  }
+}
+
+#[allow(dead_code)]
+fn read(machine: &Machine, address: i64) -> Option<i64> {
+  if address < 0 {
+    None
+  } else {
+    machine.memory.read(address as usize)
+  }
+}
+
+#[allow(dead_code)]
+fn target(address: i64) -> Option<usize> {
+  if address < 0 {
+    None
+  } else {
+    Some(address as usize)
+  }
+}
+
+// the program with every reachable instruction compiled into a match arm. Anything unusual (an
+// error, a halt, a pc that was not compiled) is handed to the interpreter for that call, and a
+// write into compiled code hands the machine over to the interpreter for good. Budgets, hooks
+// and recordings only apply while interpreting
+pub struct Compiled {
+  pub machine: Machine,
+  interpreted: bool
+}
+
+#[allow(dead_code)]
+impl Compiled {
+  pub fn new(input: Vec<i64>) -> Self {
+    Compiled {
+      machine: Machine::new(PROGRAM.to_vec(), input),
+      interpreted: false
+    }
+  }
+
+  pub fn add_input(&mut self, value: i64) {
+    self.machine.add_input(value);
+  }
+
+  // true once the program rewrote its own code
+  pub fn is_interpreted(&self) -> bool {
+    self.interpreted
+  }
+
+  pub fn run(&mut self) -> Result<Vec<i64>, VmError> {
+    let mut output = Vec::new();
+    while let State::Output(value) = self.execute()? {
+      output.push(value);
+    }
+
+    Ok(output)
+  }
+";
+
+fn value(param: &Parameter) -> String {
+  match param.mode {
+    ParameterMode::Immediate => format!("{}_i64", param.value),
+    ParameterMode::Position => format!("or_interpret!(m, interpreted, read(m, {}))", param.value),
+    ParameterMode::Relative => format!(
+      "or_interpret!(m, interpreted, m.relative_offset.checked_add({}).and_then(|address| read(m, address)))",
+      param.value
+    )
+  }
+}
+
+// stores `value`, which the caller has to have bound already
+fn store(param: &Parameter, lines: &mut Vec<String>) {
+  let address = match param.mode {
+    ParameterMode::Relative => format!("m.relative_offset.checked_add({}).and_then(target)", param.value),
+    _ => format!("target({})", param.value)
+  };
+
+  // the interpreter redoes the write and flags it
+  lines.push(format!("let address = or_interpret!(m, interpreted, {});", address));
+  lines.push(String::from("if is_code(address) {"));
+  lines.push(String::from("  return interpret(m, interpreted);"));
+  lines.push(String::from("}"));
+  lines.push(String::from("or_interpret!(m, interpreted, m.memory.write(address, value));"));
+}
+
+fn jump(param: &Parameter, lines: &mut Vec<String>, indent: &str) {
+  match param.mode {
+    ParameterMode::Immediate if param.value >= 0 => lines.push(format!("{}m.pc = {};", indent, param.value)),
+    _ => {
+      lines.push(format!("{}let to = {};", indent, value(param)));
+      lines.push(format!("{}m.pc = or_interpret!(m, interpreted, target(to));", indent));
+    }
+  }
+}
+
+// the body of one match arm, same semantics as the interpreter's dispatch
+fn compile(instruction: &Instruction) -> Vec<String> {
+  let params = &instruction.params;
+  let next = instruction.address + instruction.size();
+  let mut lines = Vec::new();
+
+  match instruction.opcode {
+    Opcode::Add | Opcode::Mul | Opcode::LessThan | Opcode::Equals => {
+      lines.push(format!("let a = {};", value(&params[0])));
+      lines.push(format!("let b = {};", value(&params[1])));
+      lines.push(String::from(match instruction.opcode {
+        Opcode::Add => "let value = or_interpret!(m, interpreted, a.checked_add(b));",
+        Opcode::Mul => "let value = or_interpret!(m, interpreted, a.checked_mul(b));",
+        Opcode::LessThan => "let value = (a < b) as i64;",
+        _ => "let value = (a == b) as i64;"
+      }));
+      store(&params[2], &mut lines);
+      lines.push(format!("m.pc = {};", next));
+    },
+    Opcode::Input => {
+      lines.push(String::from("let value = match m.input.front() {"));
+      lines.push(String::from("  Some(value) => *value,"));
+      lines.push(String::from("  None => return Ok(State::NeedsInput)"));
+      lines.push(String::from("};"));
+      store(&params[0], &mut lines);
+      lines.push(String::from("m.input.pop_front();"));
+      lines.push(format!("m.pc = {};", next));
+    },
+    Opcode::Output => {
+      lines.push(format!("let value = {};", value(&params[0])));
+      lines.push(format!("m.pc = {};", next));
+      lines.push(String::from("return Ok(State::Output(value));"));
+    },
+    Opcode::JumpIfTrue | Opcode::JumpIfFalse => {
+      let comparison = if instruction.opcode == Opcode::JumpIfTrue { "!=" } else { "==" };
+      lines.push(format!("let condition = {};", value(&params[0])));
+      lines.push(format!("if condition {} 0 {{", comparison));
+      jump(&params[1], &mut lines, "  ");
+      lines.push(String::from("} else {"));
+      lines.push(format!("  m.pc = {};", next));
+      lines.push(String::from("}"));
+    },
+    Opcode::AdjustBase => {
+      lines.push(format!("let offset = {};", value(&params[0])));
+      lines.push(String::from("m.relative_offset = or_interpret!(m, interpreted, m.relative_offset.checked_add(offset));"));
+      lines.push(format!("m.pc = {};", next));
+    },
+    // the interpreter owns the halted flag
+    Opcode::Halt => lines.push(String::from("return interpret(m, interpreted);"))
+  }
+
+  lines
+}
+
+// ranges of cells covered by the instructions, as a match pattern
+fn code_pattern(instructions: &[Instruction]) -> String {
+  let mut ranges: Vec<(usize, usize)> = Vec::new();
+  for instruction in instructions.iter() {
+    let (start, end) = (instruction.address, instruction.address + instruction.size() - 1);
+    match ranges.last_mut() {
+      // find_code can hand out instructions overlapping each other
+      Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+      _ => ranges.push((start, end))
+    }
+  }
+
+  if ranges.is_empty() {
+    return String::from("_ if false");
+  }
+  ranges
+    .iter()
+    .map(|(start, end)| format!("{}..={}", start, end))
+    .collect::<Vec<String>>()
+    .join(" | ")
+}
+
+// a Rust module exposing `Compiled`, which runs the program like `Machine` does but with every
+// reachable instruction turned into straight Rust. It depends on the intcode crate and has no
+// inner attributes, so it can be pulled in with `include!` inside a `mod` block
+pub fn transpile(program: &[i64]) -> String {
+  let mut starts: Vec<usize> = find_code(program).into_iter().collect();
+  starts.sort_unstable();
+  let instructions: Vec<Instruction> = starts
+    .iter()
+    .map(|address| Instruction::decode(program, *address).unwrap())
+    .collect();
+
+  let mut text = String::from("// generated from an Intcode program by intcode::transpile, do not edit\n\n");
+  text.push_str(PRELUDE);
+
+  text.push_str("
+  pub fn execute(&mut self) -> Result<State, VmError> {
+    if self.interpreted || self.machine.is_halted() {
+      return self.machine.execute();
+    }
+
+    let interpreted = &mut self.interpreted;
+    let m = &mut self.machine;
+    loop {
+      match m.pc {
+");
+  for instruction in instructions.iter() {
+    text.push_str(&format!("        // {}\n", instruction));
+    text.push_str(&format!("        {} => {{\n", instruction.address));
+    for line in compile(instruction) {
+      text.push_str(&format!("          {}\n", line));
+    }
+    text.push_str("        },\n");
+  }
+  text.push_str("        _ => return interpret(m, interpreted)
+      }
+    }
+  }
+}
+");
+
+  let values: Vec<String> = program.iter().map(|value| value.to_string()).collect();
+  text.push_str(&format!("\npub const PROGRAM: &[i64] = &[{}];\n", values.join(", ")));
+  text.push_str(&format!(
+    "\nfn is_code(address: usize) -> bool {{\n  matches!(address, {})\n}}\n",
+    code_pattern(&instructions)
+  ));
+
+  text
+}
+
+// transpiles the program file into a Rust source file
+pub fn transpile_file<P: AsRef<Path>, Q: AsRef<Path>>(input: P, output: Q) -> Result<(), LoadError> {
+  let program = loader::load(input)?;
+  fs::write(output, transpile(&program))?;
+
+  Ok(())
+}
+
+// for build scripts: writes `$OUT_DIR/<name>.rs` and has cargo rerun when the program changes,
+// the crate then uses it with `mod <name> { include!(concat!(env!("OUT_DIR"), "/<name>.rs")); }`
+pub fn build<P: AsRef<Path>>(input: P, name: &str) -> Result<PathBuf, LoadError> {
+  let out_dir = env::var_os("OUT_DIR").expect("OUT_DIR is only set for build scripts");
+  let output = Path::new(&out_dir).join(format!("{}.rs", name));
+
+  println!("cargo:rerun-if-changed={}", input.as_ref().display());
+  transpile_file(input, &output)?;
+
+  Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+  use super::*;
+
+  #[test]
+  fn test_compile() {
+    let instruction = Instruction::decode(&[21002, 4, 3, 4], 0).unwrap();
+
+    assert_eq!(compile(&instruction), vec![
+      "let a = or_interpret!(m, interpreted, read(m, 4));",
+      "let b = 3_i64;",
+      "let value = or_interpret!(m, interpreted, a.checked_mul(b));",
+      "let address = or_interpret!(m, interpreted, m.relative_offset.checked_add(4).and_then(target));",
+      "if is_code(address) {",
+      "  return interpret(m, interpreted);",
+      "}",
+      "or_interpret!(m, interpreted, m.memory.write(address, value));",
+      "m.pc = 4;"
+    ]);
+
+    let instruction = Instruction::decode(&[1106, 0, 7], 0).unwrap();
+    assert_eq!(compile(&instruction), vec![
+      "let condition = 0_i64;",
+      "if condition == 0 {",
+      "  m.pc = 7;",
+      "} else {",
+      "  m.pc = 3;",
+      "}"
+    ]);
+  }
+
+  #[test]
+  fn test_transpile() {
+    let text = transpile(&[1002, 4, 3, 4, 99, 7, 8]);
+
+    assert!(text.contains("        // mul [4], #3, [4]\n        0 => {\n"));
+    assert!(text.contains("        4 => {\n          return interpret(m, interpreted);\n"));
+    assert!(text.contains("pub const PROGRAM: &[i64] = &[1002, 4, 3, 4, 99, 7, 8];"));
+    assert!(text.contains("matches!(address, 0..=4)"));
+  }
+
+  #[test]
+  fn test_code_pattern() {
+    let decode = |program: &[i64], addresses: &[usize]| -> Vec<Instruction> {
+      addresses.iter().map(|address| Instruction::decode(program, *address).unwrap()).collect()
+    };
+
+    // a jump into the middle of its own operands, and a halt hidden inside an add
+    assert_eq!(code_pattern(&decode(&[1105, 1, 2, 99, 0, 0], &[0, 1, 2])), "0..=5");
+    assert_eq!(code_pattern(&decode(&[1, 99, 0, 0, 99, 99], &[0, 1, 5])), "0..=3 | 5..=5");
+    assert_eq!(code_pattern(&[]), "_ if false");
+  }
+}
@@ -0,0 +1,170 @@
+// compiles transpiled programs with cargo against this crate and checks they behave exactly like
+// the interpreter on the day 5, 7 and 9 examples
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use intcode::assemble::assemble;
+use intcode::loader::parse;
+use intcode::transpile::transpile;
+use intcode::Machine;
+
+// a program and the batches of input it gets, each batch queued before running again
+struct Case {
+  program: Vec<i64>,
+  batches: Vec<Vec<i64>>,
+  // whether the program writes into its own instructions, operands included
+  rewrites_code: bool
+}
+
+impl Case {
+  fn rewriting(mut self) -> Self {
+    self.rewrites_code = true;
+    self
+  }
+}
+
+fn case(source: &str, batches: &[&[i64]]) -> Case {
+  Case {
+    program: parse(source).unwrap(),
+    batches: batches.iter().map(|batch| batch.to_vec()).collect(),
+    rewrites_code: false
+  }
+}
+
+fn cases() -> Vec<Case> {
+  let compare = "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,\
+    1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99";
+  let self_modifying = assemble("
+    op:     add [value], #2, [value]
+            out [value]
+            jt  [done], #end
+            add #1002, #0, [op]
+            add #1, #0, [done]
+            jt  #1, #op
+    end:    hlt
+    value:  .data 3
+    done:   .data 0
+  ").unwrap();
+  // the interpreter patches the operand of the compiled out at 0 on the way back to compiled code
+  let patched_indirectly = assemble("
+    start:  out [a]
+            jt  [flag], #end
+            add #1, #0, [flag]
+            jt  [one], [t1]
+    show:   out #77
+            jt  #1, #start
+    end:    hlt
+    a:      .data 555
+    b:      .data 11
+    flag:   .data 0
+    one:    .data 1
+    t1:     .data patch
+    patch:  add #b, #0, [1]
+            jt  #1, #show
+  ").unwrap();
+
+  vec![
+    // day 5
+    case("1002,4,3,4,33", &[&[]]),
+    case("3,9,8,9,10,9,4,9,99,-1,8", &[&[8], &[]]),
+    case("3,3,1107,-1,8,3,4,3,99", &[&[3]]).rewriting(),
+    case("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9", &[&[], &[0]]),
+    case("3,3,1105,-1,9,1101,0,0,12,4,12,99,1", &[&[5]]).rewriting(),
+    case(compare, &[&[7]]),
+    case(compare, &[&[8]]),
+    case(compare, &[&[9]]),
+    // day 7, including the feedback loop programs fed one value at a time
+    case("3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0", &[&[4, 0]]),
+    case("3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0", &[&[0, 1]]),
+    case("3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5", &[
+      &[9, 0], &[5], &[17], &[100], &[1000], &[]
+    ]),
+    // day 9
+    case("109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99", &[&[]]),
+    case("1102,34915192,34915192,7,4,7,99,0", &[&[]]),
+    case("104,1125899906842624,99", &[&[]]),
+    case("109,-5,203,0,99", &[&[1]]),
+    // errors and rewritten code are left to the interpreter
+    case("1,-1,0,0,99", &[&[]]),
+    case("1101,9223372036854775807,1,0,99", &[&[]]),
+    case("109,9223372036854775807,109,1,99", &[&[]]),
+    Case { program: self_modifying, batches: vec![Vec::new()], rewrites_code: true },
+    Case { program: patched_indirectly, batches: vec![Vec::new()], rewrites_code: true }
+  ]
+}
+
+// a scratch package depending on this crate by path, so cargo builds it from the current source
+fn package(directory: &Path, source: &str) {
+  let manifest = format!(
+    "[package]\nname = \"transpiled\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+    [dependencies]\nintcode = {{ path = {:?} }}\n\n[workspace]\n",
+    env!("CARGO_MANIFEST_DIR")
+  );
+
+  fs::create_dir_all(directory.join("src")).unwrap();
+  fs::write(directory.join("Cargo.toml"), manifest).unwrap();
+  fs::write(directory.join("src").join("main.rs"), source).unwrap();
+}
+
+#[test]
+fn test_matches_interpreter() {
+  let cases = cases();
+  let mut source = String::new();
+  let mut expected = Vec::new();
+
+  for (index, case) in cases.iter().enumerate() {
+    source.push_str(&format!("mod case_{} {{\n{}}}\n\n", index, transpile(&case.program)));
+
+    let mut machine = Machine::new(case.program.clone(), Vec::new());
+    for batch in case.batches.iter() {
+      batch.iter().for_each(|value| machine.add_input(*value));
+      expected.push(format!(
+        "{} {:?} {} {} {:?} {}",
+        index,
+        machine.run(),
+        machine.pc,
+        machine.relative_offset,
+        machine.memory.as_slice(),
+        machine.is_halted()
+      ));
+    }
+    expected.push(case.rewrites_code.to_string());
+  }
+
+  source.push_str("fn main() {\n");
+  for (index, case) in cases.iter().enumerate() {
+    source.push_str(&format!("  let mut compiled = case_{}::Compiled::new(Vec::new());\n", index));
+    for batch in case.batches.iter() {
+      source.push_str(&format!("  for value in {:?}.iter() {{\n", batch));
+      source.push_str("    compiled.add_input(*value);\n  }\n");
+      source.push_str("  let output = compiled.run();\n");
+      source.push_str(&format!(
+        "  println!(\"{} {{:?}} {{}} {{}} {{:?}} {{}}\", output, compiled.machine.pc, \
+        compiled.machine.relative_offset, compiled.machine.memory.as_slice(), compiled.machine.is_halted());\n",
+        index
+      ));
+    }
+    source.push_str("  println!(\"{}\", compiled.is_interpreted());\n");
+  }
+  source.push_str("}\n");
+
+  // kept under the target directory so later runs only rebuild what changed
+  let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("transpiled");
+  package(&directory, &source);
+
+  let run = Command::new(env::var("CARGO").unwrap_or_else(|_| String::from("cargo")))
+    .arg("run")
+    .arg("--quiet")
+    .arg("--offline")
+    .current_dir(&directory)
+    .env("CARGO_TARGET_DIR", directory.join("target"))
+    .env("RUSTFLAGS", "-D warnings")
+    .output()
+    .unwrap();
+  assert!(run.status.success(), "{}", String::from_utf8_lossy(&run.stderr));
+  let actual: Vec<String> = String::from_utf8(run.stdout).unwrap().lines().map(String::from).collect();
+
+  assert_eq!(actual, expected);
+}